@@ -1,12 +1,25 @@
+use std::time::Duration;
+
 use anyhow::Result;
-use copilot_client::CopilotClient;
+use copilot_client::{CopilotClient, CopilotError};
 use genai::{
-    Client,
+    Client, ModelIden, ServiceTarget, WebConfig,
+    adapter::AdapterKind,
     chat::{ChatMessage, ChatOptions, ChatRequest},
+    resolver::{AuthData, Endpoint},
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    config::{AppConfig, ProviderConfig, same_provider},
+    error::AppError,
+    message::{self, BODY_WIDTH, CommitMessage},
+    prompts::Prompt,
+    style::{InvalidMessagePolicy, StyleGuide},
 };
-use tracing::{debug, info};
 
-use crate::{config::AppConfig, error::AppError};
+/// How often the model is asked to correct an invalid message.
+const MAX_REPROMPTS: u32 = 2;
 
 pub struct AiClient {
     client: Client,
@@ -17,7 +30,41 @@ pub struct AiClient {
 
 impl AiClient {
     pub fn new(config: &AppConfig) -> Self {
-        let client = Client::default();
+        let web_config = WebConfig::default()
+            .with_timeout(Duration::from_secs(config.ai.timeout_secs))
+            .with_connect_timeout(Duration::from_secs(config.ai.connect_timeout_secs));
+        let mut builder = Client::builder().with_web_config(web_config);
+
+        let provider = config.provider();
+        // Keys and endpoints only go to the configured provider, never to another
+        // vendor picked by a `--model` override
+        let adapter = AdapterKind::from_lower_str(&config.ai.provider);
+
+        // Without a configured key genai falls back to the provider's env variable
+        if let Some(adapter) = adapter
+            && let Some(api_key) = provider.and_then(ProviderConfig::api_key)
+        {
+            let api_key = api_key.to_string();
+            builder = builder.with_auth_resolver_fn(move |model_iden: ModelIden| {
+                Ok(same_provider(model_iden.adapter_kind, adapter)
+                    .then(|| AuthData::from_single(api_key.clone())))
+            });
+        }
+
+        if let Some(adapter) = adapter
+            && let Some(base_url) = provider.and_then(|p| p.base_url.as_deref())
+        {
+            // genai joins request paths onto the endpoint, so it needs a trailing slash
+            let base_url = format!("{}/", base_url.trim_end_matches('/'));
+            builder = builder.with_service_target_resolver_fn(move |mut target: ServiceTarget| {
+                if same_provider(target.model.adapter_kind, adapter) {
+                    target.endpoint = Endpoint::from_owned(base_url.clone());
+                }
+                Ok(target)
+            });
+        }
+
+        let client = builder.build();
 
         Self {
             client,
@@ -26,55 +73,123 @@ impl AiClient {
         }
     }
 
+    /// Generate a message, clean it up and enforce the style according to its
+    /// `on_invalid` policy.
     pub async fn generate_commit_message(
         &self,
-        diff: &str,
-        status: &str,
-        context: Option<&str>,
+        prompt: &Prompt,
+        style_guide: &StyleGuide,
         model_override: Option<&str>,
-    ) -> Result<String> {
-        let model = model_override.unwrap_or(&self.config.ai.model);
+    ) -> Result<CommitMessage> {
+        let commit_message = self
+            .generate(prompt, style_guide, model_override, |response| {
+                let mut commit_message = CommitMessage::parse(&message::clean_response(response));
+                commit_message.wrap_body(BODY_WIDTH);
+                let problems = style_guide.enforce(&mut commit_message);
+                Ok((commit_message, problems))
+            })
+            .await?;
+        info!("Generated commit message: {}", commit_message);
+        Ok(commit_message)
+    }
 
-        debug!("Generating commit message with model: {}", model);
+    /// Ask for an answer and `parse` it into a value and the style problems left
+    /// after fixing, then apply the `on_invalid` policy to those problems.
+    pub async fn generate<T>(
+        &self,
+        prompt: &Prompt,
+        style_guide: &StyleGuide,
+        model_override: Option<&str>,
+        parse: impl Fn(&str) -> Result<(T, Vec<String>)>,
+    ) -> Result<T> {
+        let mut prompt = prompt.clone();
+        let mut reprompts = 0;
+
+        loop {
+            let response = self.complete(&prompt, model_override).await?;
+            let (value, problems) = parse(&response)?;
+            if problems.is_empty() || style_guide.on_invalid == InvalidMessagePolicy::Fix {
+                return Ok(value);
+            }
+
+            if style_guide.on_invalid == InvalidMessagePolicy::Reprompt && reprompts < MAX_REPROMPTS
+            {
+                reprompts += 1;
+                warn!(
+                    "Generated message is invalid ({}), asking again (attempt {}/{})",
+                    problems.join("; "),
+                    reprompts,
+                    MAX_REPROMPTS
+                );
+                prompt.user = format!(
+                    "{}\n\nYour previous answer was:\n```\n{}\n```\nIt is invalid: {}.\nReply with a corrected answer in the same format only.",
+                    prompt.user,
+                    response.trim(),
+                    problems.join("; ")
+                );
+                continue;
+            }
+
+            return Err(AppError::InvalidCommitMessage(problems).into());
+        }
+    }
 
-        // Use GitHub Copilot client if provider is github
-        if self.config.ai.provider == "github" {
-            return self
-                .generate_with_copilot(diff, status, context, model)
-                .await;
+    /// Send a rendered prompt to the configured provider, retrying transient failures.
+    pub async fn complete(&self, prompt: &Prompt, model_override: Option<&str>) -> Result<String> {
+        let model = model_override.unwrap_or(self.config.model());
+
+        debug!("Sending prompt with model: {}", model);
+
+        let mut attempt = 0;
+        loop {
+            let request = self.request_completion(prompt, model);
+            let result = tokio::time::timeout(self.request_timeout(), request)
+                .await
+                .unwrap_or(Err(AppError::Timeout(self.config.ai.timeout_secs)));
+
+            match result {
+                Ok(response) => return Ok(response),
+                Err(e) if e.is_retryable() && attempt < self.config.ai.max_retries => {
+                    let delay = self.retry_delay(attempt);
+                    attempt += 1;
+                    warn!(
+                        "AI request failed ({}), retrying in {:?} (attempt {}/{})",
+                        e, delay, attempt, self.config.ai.max_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e.into()),
+            }
         }
+    }
 
-        // Use genai client for other providers
-        let mut messages = vec![ChatMessage::system(&self.config.prompts.system_prompt)];
+    fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.ai.timeout_secs)
+    }
 
-        // Add context if provided
-        if let Some(ctx) = context {
-            messages.push(ChatMessage::user(format!("Context: {ctx}\n\n")));
-        }
+    /// Exponential backoff: `retry_backoff_ms * 2^attempt`.
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        let factor = 1u64 << attempt.min(16);
+        Duration::from_millis(self.config.ai.retry_backoff_ms.saturating_mul(factor))
+    }
 
-        // Add git status
-        messages.push(ChatMessage::user(format!(
-            "`git status`:\n```\n{}\n```\n\n",
-            status.trim()
-        )));
-
-        // Add git diff
-        if !diff.trim().is_empty() {
-            messages.push(ChatMessage::user(format!(
-                "`git diff --staged`:\n```diff\n{}\n```\n\n",
-                diff.trim()
-            )));
+    async fn request_completion(&self, prompt: &Prompt, model: &str) -> Result<String, AppError> {
+        // Use GitHub Copilot client if provider is github
+        if self.config.ai.provider == "github" {
+            return self.complete_with_copilot(prompt, model).await;
         }
 
-        messages.push(ChatMessage::user(
-            "Generate a conventional commit message based on the changes above:",
-        ));
-
-        let chat_request = ChatRequest::new(messages);
+        // Use genai client for other providers
+        let chat_request = ChatRequest::new(vec![
+            ChatMessage::system(&prompt.system),
+            ChatMessage::user(&prompt.user),
+        ]);
 
         let chat_options = ChatOptions {
             temperature: Some(f64::from(self.config.ai.temperature)),
-            max_tokens: Some(self.config.ai.max_tokens),
+            max_tokens: Some(prompt.max_tokens.map_or(self.config.ai.max_tokens, |t| {
+                t.max(self.config.ai.max_tokens)
+            })),
             ..Default::default()
         };
 
@@ -86,87 +201,78 @@ impl AiClient {
         let response = self
             .client
             .exec_chat(model, chat_request, Some(&chat_options))
-            .await?;
+            .await
+            .map_err(|e| self.classify_genai_error(e))?;
 
-        let commit_message = response
+        Ok(response
             .first_text()
             .ok_or(AppError::NoResponseFromAi)?
             .trim()
-            .to_string();
-
-        info!("Generated commit message: {}", commit_message);
-
-        Ok(commit_message)
+            .to_string())
     }
 
-    async fn generate_with_copilot(
+    async fn complete_with_copilot(
         &self,
-        diff: &str,
-        status: &str,
-        context: Option<&str>,
+        prompt: &Prompt,
         model: &str,
-    ) -> Result<String> {
+    ) -> Result<String, AppError> {
         // Initialize GitHub Copilot client
         let editor_version = "ai-commit/0.1.0".to_string();
         let copilot_client = CopilotClient::from_env_with_models(editor_version)
             .await
-            .map_err(|e| AppError::AuthenticationError(e.to_string()))?;
-
-        let mut messages = vec![copilot_client::Message {
-            role: "system".to_string(),
-            content: self.config.prompts.system_prompt.clone(),
-        }];
-
-        // Add context if provided
-        if let Some(ctx) = context {
-            messages.push(copilot_client::Message {
-                role: "user".to_string(),
-                content: format!("Context: {ctx}\n\n"),
-            });
-        }
-
-        // Add git status
-        messages.push(copilot_client::Message {
-            role: "user".to_string(),
-            content: format!("`git status`:\n```\n{}\n```\n\n", status.trim()),
-        });
-
-        // Add git diff
-        if !diff.trim().is_empty() {
-            messages.push(copilot_client::Message {
+            .map_err(classify_copilot_error)?;
+
+        let messages = vec![
+            copilot_client::Message {
+                role: "system".to_string(),
+                content: prompt.system.clone(),
+            },
+            copilot_client::Message {
                 role: "user".to_string(),
-                content: format!("`git diff --staged`:\n```diff\n{}\n```\n\n", diff.trim()),
-            });
-        }
-
-        messages.push(copilot_client::Message {
-            role: "user".to_string(),
-            content: "Generate a conventional commit message based on the changes above:"
-                .to_string(),
-        });
+                content: prompt.user.clone(),
+            },
+        ];
 
         debug!("Sending request to GitHub Copilot with model: {}", model);
 
         let response = copilot_client
             .chat_completion(messages, model.to_string())
             .await
-            .map_err(|e| AppError::AuthenticationError(e.to_string()))?;
+            .map_err(classify_copilot_error)?;
 
-        let commit_message = response
+        Ok(response
             .choices
             .first()
             .ok_or(AppError::NoResponseFromAi)?
             .message
             .content
             .trim()
-            .to_string();
+            .to_string())
+    }
 
-        info!(
-            "Generated commit message with GitHub Copilot: {}",
-            commit_message
-        );
+    /// Map transport-level genai failures onto retryable `AppError` variants.
+    fn classify_genai_error(&self, error: genai::Error) -> AppError {
+        let webc_error = match &error {
+            genai::Error::WebModelCall { webc_error, .. }
+            | genai::Error::WebAdapterCall { webc_error, .. } => webc_error,
+            _ => return AppError::GenAi(error),
+        };
 
-        Ok(commit_message)
+        match webc_error {
+            genai::webc::Error::ResponseFailedStatus { status, .. } if status.as_u16() == 429 => {
+                AppError::RateLimited(error.to_string())
+            }
+            genai::webc::Error::ResponseFailedStatus { status, .. } if status.is_server_error() => {
+                AppError::ServiceUnavailable(error.to_string())
+            }
+            genai::webc::Error::Reqwest(e) if e.is_timeout() => {
+                AppError::Timeout(self.config.ai.timeout_secs)
+            }
+            genai::webc::Error::Reqwest(e) if e.is_connect() || e.is_request() => {
+                AppError::Network(error.to_string())
+            }
+            _ => AppError::GenAi(error),
+        }
     }
 
     pub fn list_models(&self) -> Result<Vec<String>> {
@@ -218,3 +324,92 @@ impl AiClient {
         Ok(models)
     }
 }
+
+fn classify_copilot_error(error: CopilotError) -> AppError {
+    let msg = match error {
+        CopilotError::HttpError(msg) => msg,
+        CopilotError::TokenError(_) => return AppError::AuthenticationError(error.to_string()),
+        // Invalid models and unparsable responses are not the credentials' fault
+        CopilotError::InvalidModel(_) | CopilotError::Other(_) => {
+            return AppError::ProviderError(error.to_string());
+        }
+    };
+
+    match http_status(&msg) {
+        Some(401 | 403) => AppError::AuthenticationError(msg),
+        Some(429) => AppError::RateLimited(msg),
+        Some(status) if status >= 500 => AppError::ServiceUnavailable(msg),
+        Some(_) => AppError::RequestRejected(msg),
+        None => AppError::Network(msg),
+    }
+}
+
+/// Status code of a reqwest status failure, formatted as
+/// "HTTP status client error (429 Too Many Requests) for url (...)".
+fn http_status(msg: &str) -> Option<u16> {
+    let (_, rest) = msg.split_once("HTTP status ")?;
+    let (_, rest) = rest.split_once('(')?;
+    rest.get(..3)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(msg: &str) -> AppError {
+        classify_copilot_error(CopilotError::HttpError(msg.to_string()))
+    }
+
+    #[test]
+    fn copilot_http_errors_are_classified_by_status() {
+        let status = |code: &str| {
+            format!(
+                "HTTP status client error ({code}) for url (https://api.githubcopilot.com/chat/completions)"
+            )
+        };
+
+        assert!(matches!(
+            classify(&status("401 Unauthorized")),
+            AppError::AuthenticationError(_)
+        ));
+        assert!(matches!(
+            classify(&status("403 Forbidden")),
+            AppError::AuthenticationError(_)
+        ));
+        assert!(matches!(
+            classify(&status("429 Too Many Requests")),
+            AppError::RateLimited(_)
+        ));
+        assert!(matches!(
+            classify(&status("400 Bad Request")),
+            AppError::RequestRejected(_)
+        ));
+        assert!(matches!(
+            classify("HTTP status server error (503 Service Unavailable) for url (https://x)"),
+            AppError::ServiceUnavailable(_)
+        ));
+        assert!(matches!(
+            classify(
+                "error sending request for url (https://api.githubcopilot.com/chat/completions)"
+            ),
+            AppError::Network(_)
+        ));
+
+        assert!(matches!(
+            classify_copilot_error(CopilotError::TokenError("no GitHub token".into())),
+            AppError::AuthenticationError(_)
+        ));
+        assert!(matches!(
+            classify_copilot_error(CopilotError::Other("expected value at line 1".into())),
+            AppError::ProviderError(_)
+        ));
+        assert!(matches!(
+            classify_copilot_error(CopilotError::InvalidModel("gpt-9".into())),
+            AppError::ProviderError(_)
+        ));
+
+        assert!(!classify(&status("401 Unauthorized")).is_retryable());
+        assert!(!classify(&status("400 Bad Request")).is_retryable());
+        assert!(classify("error sending request for url (https://x)").is_retryable());
+    }
+}
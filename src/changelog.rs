@@ -0,0 +1,517 @@
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    error::AppError,
+    git::CommitInfo,
+    message::{self, CommitMessage, ConventionalSubject},
+};
+
+const CHANGELOG_HEADER: &str = "# Changelog
+
+All notable changes to this project will be documented in this file.
+
+The format is based on [Keep a Changelog](https://keepachangelog.com/en/1.1.0/).
+";
+
+/// Heading for changes not yet part of a release.
+pub const UNRELEASED: &str = "Unreleased";
+
+/// How `ai-commit changelog` prints a release.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ChangelogFormat {
+    /// Keep a Changelog Markdown
+    #[default]
+    Markdown,
+    /// Entries grouped by section as JSON
+    Json,
+}
+
+/// Keep a Changelog section, in the order sections are listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Category {
+    Added,
+    Changed,
+    Deprecated,
+    Removed,
+    Fixed,
+    Security,
+}
+
+impl Category {
+    /// Section for a commit, or `None` for changes users do not see, such as
+    /// `docs`, `test` or `chore` commits that break nothing.
+    fn of(subject: Option<&ConventionalSubject>, breaking: bool) -> Option<Self> {
+        // Commits outside Conventional Commits cannot be told apart, so all are kept
+        let Some(subject) = subject else {
+            return Some(Self::Changed);
+        };
+
+        let description = subject.description.to_lowercase();
+        let commit_type = subject.commit_type.to_lowercase();
+        if commit_type == "security" || subject.scope.as_deref() == Some("security") {
+            return Some(Self::Security);
+        }
+        if commit_type != "fix" && description.starts_with("deprecate") {
+            return Some(Self::Deprecated);
+        }
+        if commit_type != "fix"
+            && (description.starts_with("remove") || description.starts_with("drop"))
+        {
+            return Some(Self::Removed);
+        }
+
+        match commit_type.as_str() {
+            "feat" => Some(Self::Added),
+            "fix" => Some(Self::Fixed),
+            "perf" | "refactor" | "revert" => Some(Self::Changed),
+            _ if breaking => Some(Self::Changed),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// One line of the changelog, made from a commit.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangelogEntry {
+    /// Abbreviated id of the commit.
+    pub id: String,
+    pub category: Category,
+    #[serde(rename = "type")]
+    pub commit_type: Option<String>,
+    pub scope: Option<String>,
+    pub description: String,
+    /// Marked with `!` or a `BREAKING CHANGE` footer.
+    pub breaking: bool,
+    /// Commit body, which the model can draw on when rewriting the entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+/// The changes of one release, grouped into Keep a Changelog sections.
+#[derive(Debug, Clone, Serialize)]
+pub struct Release {
+    /// Version heading, `Unreleased` when the range does not end on a release.
+    pub version: String,
+    /// Release date as `YYYY-MM-DD`, absent for unreleased changes.
+    pub date: Option<String>,
+    pub sections: BTreeMap<Category, Vec<ChangelogEntry>>,
+}
+
+#[derive(Deserialize)]
+struct RawNotes {
+    entries: Vec<RawNote>,
+}
+
+#[derive(Deserialize)]
+struct RawNote {
+    id: String,
+    text: String,
+}
+
+impl Release {
+    /// Sort `commits` (newest first) into sections, each grouped by scope.
+    pub fn from_commits(version: String, date: Option<String>, commits: &[CommitInfo]) -> Self {
+        let mut sections: BTreeMap<Category, Vec<ChangelogEntry>> = BTreeMap::new();
+        for commit in commits {
+            let message = CommitMessage::parse(&commit.message);
+            let subject = ConventionalSubject::parse(&message.subject);
+            let breaking = subject.as_ref().is_some_and(|s| s.breaking)
+                || message
+                    .footers
+                    .iter()
+                    .any(|footer| footer.token == "BREAKING CHANGE");
+            let Some(category) = Category::of(subject.as_ref(), breaking) else {
+                continue;
+            };
+
+            let (commit_type, scope, description) = match subject {
+                Some(subject) => (
+                    Some(subject.commit_type),
+                    subject.scope,
+                    subject.description,
+                ),
+                None => (None, None, message.subject.clone()),
+            };
+            sections.entry(category).or_default().push(ChangelogEntry {
+                id: commit.short_id(),
+                category,
+                commit_type,
+                scope,
+                description,
+                breaking,
+                body: message.body,
+            });
+        }
+
+        for entries in sections.values_mut() {
+            // Stable, so commits keep their order within a scope
+            entries.sort_by(|a, b| a.scope.cmp(&b.scope));
+        }
+
+        Self {
+            version,
+            date,
+            sections,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty()
+    }
+
+    /// All entries, section by section.
+    pub fn entries(&self) -> Vec<ChangelogEntry> {
+        self.sections.values().flatten().cloned().collect()
+    }
+
+    /// Replace entry descriptions with the release notes in the model's JSON answer.
+    ///
+    /// Entries the model left out keep their description; entries it answered
+    /// with an empty text are dropped as not relevant to users.
+    pub fn apply_notes(&mut self, response: &str) -> Result<()> {
+        let Some(json) = message::json_object(response) else {
+            return Err(
+                AppError::InvalidReleaseNotes("no JSON object in the answer".into()).into(),
+            );
+        };
+        let raw: RawNotes =
+            serde_json::from_str(json).map_err(|e| AppError::InvalidReleaseNotes(e.to_string()))?;
+        let notes: BTreeMap<String, String> = raw
+            .entries
+            .into_iter()
+            .map(|note| (note.id, note.text.trim().to_string()))
+            .collect();
+
+        let mut missing = Vec::new();
+        for entries in self.sections.values_mut() {
+            entries.retain_mut(|entry| match notes.get(&entry.id) {
+                Some(text) if text.is_empty() => false,
+                Some(text) => {
+                    entry.description.clone_from(text);
+                    true
+                }
+                None => {
+                    missing.push(entry.id.clone());
+                    true
+                }
+            });
+        }
+        self.sections.retain(|_, entries| !entries.is_empty());
+        if !missing.is_empty() {
+            warn!(
+                "Release notes leave out commits {}, keeping them as they are",
+                missing.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// The `## [version] - date` heading line.
+    fn heading(&self) -> String {
+        match &self.date {
+            Some(date) => format!("## [{}] - {date}", self.version),
+            None => format!("## [{}]", self.version),
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = self.heading();
+        markdown.push('\n');
+        for (category, entries) in &self.sections {
+            markdown.push_str(&format!("\n### {category}\n\n"));
+            for entry in entries {
+                markdown.push_str("- ");
+                if entry.breaking {
+                    markdown.push_str("**BREAKING:** ");
+                }
+                if let Some(scope) = &entry.scope {
+                    markdown.push_str(&format!("**{scope}:** "));
+                }
+                markdown.push_str(&capitalize(&entry.description));
+                markdown.push('\n');
+            }
+        }
+        markdown
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Add the release above the newest one in the changelog at `path`, creating the
+    /// file with the Keep a Changelog preamble if needed.
+    ///
+    /// An empty section with the same heading, like a placeholder `## [Unreleased]`,
+    /// is replaced; one with content is left alone and reported as an error.
+    pub fn prepend_to(&self, path: &Path) -> Result<()> {
+        let existing = if path.is_file() {
+            std::fs::read_to_string(path)?
+        } else {
+            CHANGELOG_HEADER.to_string()
+        };
+        let mut lines: Vec<&str> = existing.lines().collect();
+
+        let version_heading = format!("## [{}]", self.version);
+        if let Some(start) = lines
+            .iter()
+            .position(|line| line.starts_with(&version_heading))
+        {
+            let end = lines[start + 1..]
+                .iter()
+                .position(|line| line.starts_with("## "))
+                .map_or(lines.len(), |offset| start + 1 + offset);
+            if lines[start + 1..end]
+                .iter()
+                .any(|line| !line.trim().is_empty())
+            {
+                return Err(AppError::ReleaseExists(
+                    path.display().to_string(),
+                    self.version.clone(),
+                )
+                .into());
+            }
+            lines.drain(start..end);
+        }
+
+        // Unreleased changes stay on top
+        let insert_at = lines
+            .iter()
+            .position(|line| {
+                line.starts_with("## ")
+                    && (self.version == UNRELEASED || !line.starts_with("## [Unreleased]"))
+            })
+            .unwrap_or(lines.len());
+        let mut content = lines[..insert_at].join("\n").trim_end().to_string();
+        content.push_str("\n\n");
+        content.push_str(&self.to_markdown());
+        if insert_at < lines.len() {
+            content.push('\n');
+            content.push_str(&lines[insert_at..].join("\n"));
+            content = content.trim_end().to_string();
+            content.push('\n');
+        }
+
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// `support empty input` becomes `Support empty input`.
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn commit(n: u8, message: &str) -> CommitInfo {
+        CommitInfo {
+            id: git2::Oid::from_bytes(&[n; 20]).expect("valid oid"),
+            message: message.to_string(),
+        }
+    }
+
+    fn release(version: &str, date: Option<&str>, messages: &[&str]) -> Release {
+        let commits: Vec<CommitInfo> = (1..)
+            .zip(messages)
+            .map(|(n, message)| commit(n, message))
+            .collect();
+        Release::from_commits(version.to_string(), date.map(ToString::to_string), &commits)
+    }
+
+    fn descriptions(release: &Release, category: Category) -> Vec<&str> {
+        release
+            .sections
+            .get(&category)
+            .map_or_else(Vec::new, |entries| {
+                entries.iter().map(|e| e.description.as_str()).collect()
+            })
+    }
+
+    #[test]
+    fn commits_are_sorted_into_categories() {
+        let release = release(
+            UNRELEASED,
+            None,
+            &[
+                "feat(cli): add --json output",
+                "fix: handle empty ranges",
+                "feat!: rename the config file",
+                "docs: explain ranges",
+                "ci!: require Rust 1.85",
+                "chore: drop the old release script",
+                "refactor: split the parser",
+                "Update the README",
+                "feat: deprecate --all",
+                "feat!: remove the legacy api key",
+                "fix(security): escape branch names",
+                "test: cover merges\n\nBREAKING CHANGE: the fixtures moved",
+            ],
+        );
+
+        assert_eq!(
+            descriptions(&release, Category::Added),
+            ["rename the config file", "add --json output"]
+        );
+        assert_eq!(
+            descriptions(&release, Category::Fixed),
+            ["handle empty ranges"]
+        );
+        assert_eq!(
+            descriptions(&release, Category::Changed),
+            [
+                "require Rust 1.85",
+                "split the parser",
+                "Update the README",
+                "cover merges"
+            ]
+        );
+        assert_eq!(
+            descriptions(&release, Category::Deprecated),
+            ["deprecate --all"]
+        );
+        assert_eq!(
+            descriptions(&release, Category::Removed),
+            ["drop the old release script", "remove the legacy api key"]
+        );
+        assert_eq!(
+            descriptions(&release, Category::Security),
+            ["escape branch names"]
+        );
+
+        let breaking: Vec<String> = release
+            .entries()
+            .into_iter()
+            .filter(|entry| entry.breaking)
+            .map(|entry| entry.description)
+            .collect();
+        assert_eq!(
+            breaking,
+            [
+                "rename the config file",
+                "require Rust 1.85",
+                "cover merges",
+                "remove the legacy api key",
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown_marks_scopes_and_breaking_changes() {
+        let release = release(
+            "1.2.0",
+            Some("2024-02-29"),
+            &["feat(cli): add --json output", "fix!: reject empty ranges"],
+        );
+
+        assert_eq!(
+            release.to_markdown(),
+            "## [1.2.0] - 2024-02-29\n\n### Added\n\n- **cli:** Add --json output\n\n\
+             ### Fixed\n\n- **BREAKING:** Reject empty ranges\n"
+        );
+    }
+
+    #[test]
+    fn release_is_prepended_below_the_preamble() {
+        let dir = TempDir::new().expect("temp dir");
+        let path = dir.path().join("CHANGELOG.md");
+
+        release("1.0.0", Some("2024-01-01"), &["feat: first"])
+            .prepend_to(&path)
+            .expect("create changelog");
+        release("1.1.0", Some("2024-02-29"), &["fix: second"])
+            .prepend_to(&path)
+            .expect("prepend release");
+
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("read changelog"),
+            format!(
+                "{CHANGELOG_HEADER}\n## [1.1.0] - 2024-02-29\n\n### Fixed\n\n- Second\n\n\
+                 ## [1.0.0] - 2024-01-01\n\n### Added\n\n- First\n"
+            )
+        );
+    }
+
+    #[test]
+    fn releases_go_below_unreleased_changes() {
+        let dir = TempDir::new().expect("temp dir");
+        let path = dir.path().join("CHANGELOG.md");
+        std::fs::write(
+            &path,
+            format!(
+                "{CHANGELOG_HEADER}\n## [Unreleased]\n\n### Added\n\n- Work in progress\n\n\
+                 ## [1.0.0] - 2024-01-01\n\n### Added\n\n- First\n"
+            ),
+        )
+        .expect("write changelog");
+
+        release("1.1.0", Some("2024-02-29"), &["fix: second"])
+            .prepend_to(&path)
+            .expect("prepend release");
+
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("read changelog"),
+            format!(
+                "{CHANGELOG_HEADER}\n## [Unreleased]\n\n### Added\n\n- Work in progress\n\n\
+                 ## [1.1.0] - 2024-02-29\n\n### Fixed\n\n- Second\n\n\
+                 ## [1.0.0] - 2024-01-01\n\n### Added\n\n- First\n"
+            )
+        );
+    }
+
+    #[test]
+    fn empty_sections_are_replaced_and_filled_ones_kept() {
+        let dir = TempDir::new().expect("temp dir");
+        let path = dir.path().join("CHANGELOG.md");
+        std::fs::write(
+            &path,
+            format!(
+                "{CHANGELOG_HEADER}\n## [Unreleased]\n\n## [1.0.0] - 2024-01-01\n\n\
+                 ### Added\n\n- First\n"
+            ),
+        )
+        .expect("write changelog");
+
+        release(UNRELEASED, None, &["feat: second"])
+            .prepend_to(&path)
+            .expect("replace placeholder");
+        let expected = format!(
+            "{CHANGELOG_HEADER}\n## [Unreleased]\n\n### Added\n\n- Second\n\n\
+             ## [1.0.0] - 2024-01-01\n\n### Added\n\n- First\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("read changelog"),
+            expected
+        );
+
+        let error = release(UNRELEASED, None, &["feat: third"])
+            .prepend_to(&path)
+            .expect_err("section has content");
+        assert!(matches!(
+            error.downcast_ref::<AppError>(),
+            Some(AppError::ReleaseExists(_, version)) if version == UNRELEASED
+        ));
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("read changelog"),
+            expected
+        );
+    }
+}
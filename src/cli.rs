@@ -1,14 +1,44 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::{changelog::ChangelogFormat, git::AutoStage, message::BodyMode, style::MessageStyle};
 
 #[derive(Parser, Debug)]
 #[command(name = "ai-commit")]
 #[command(about = "AI-powered Git commit message generator")]
 #[command(version)]
 pub struct Cli {
+    /// Run as if started in this directory
+    #[arg(short = 'C', long = "repo", value_name = "PATH", global = true)]
+    pub directory: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub commit: CommitArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+/// Options for the default commit command.
+#[derive(Args, Debug)]
+pub struct CommitArgs {
     /// Stage all files before committing
     #[arg(short, long)]
     pub all: bool,
 
+    /// Choose the hunks to stage interactively before committing
+    #[arg(short, long, conflicts_with = "all")]
+    pub patch: bool,
+
+    /// Stage changed files matching this glob (repeatable)
+    #[arg(long, value_name = "GLOB", conflicts_with = "patch")]
+    pub include: Vec<String>,
+
+    /// Do not stage changed files matching this glob (repeatable)
+    #[arg(long, value_name = "GLOB", conflicts_with = "patch")]
+    pub exclude: Vec<String>,
+
     /// Automatically accept the generated commit message
     #[arg(short, long)]
     pub yes: bool,
@@ -21,8 +51,33 @@ pub struct Cli {
     #[arg(short, long)]
     pub context: Option<String>,
 
-    #[command(subcommand)]
-    pub command: Option<Commands>,
+    /// Commit message style (overrides `git.style`)
+    #[arg(long, value_enum)]
+    pub style: Option<MessageStyle>,
+
+    /// Whether to generate a message body (overrides `git.body`)
+    #[arg(long, value_enum)]
+    pub body: Option<BodyMode>,
+
+    /// Add a Signed-off-by trailer with the committer identity
+    #[arg(short, long)]
+    pub signoff: bool,
+
+    /// Add a Co-authored-by trailer (repeatable)
+    #[arg(long = "co-author", value_name = "NAME <EMAIL>")]
+    pub co_authors: Vec<String>,
+
+    /// Regenerate the message of HEAD and amend it with any staged changes
+    #[arg(long)]
+    pub amend: bool,
+
+    /// Print a message for all uncommitted changes without staging or committing
+    #[arg(long, conflicts_with_all = ["all", "patch", "include", "exclude", "amend", "yes"])]
+    pub worktree: bool,
+
+    /// Amend even if HEAD has already been pushed
+    #[arg(long, requires = "amend")]
+    pub force: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -36,6 +91,202 @@ pub enum Commands {
     Models,
     /// Authenticate with GitHub Copilot
     Auth,
+    /// Inspect prompt templates
+    Prompt {
+        #[command(subcommand)]
+        action: PromptAction,
+    },
+    /// Regenerate the messages of the commits in a range and rewrite them
+    Reword(RewordArgs),
+    /// Write one message summarising the commits in a range
+    SquashMsg(SquashArgs),
+    /// Split the staged changes into several logical commits
+    Split(SplitArgs),
+    /// Stash the uncommitted changes under a generated description
+    Stash(StashArgs),
+    /// Write a pull request title and description for the current branch
+    Pr(PrArgs),
+    /// Write a Keep a Changelog section for the commits in a range
+    Changelog(ChangelogArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ChangelogArgs {
+    /// Commits to include: `from..to`, or `from` for `from..HEAD`
+    pub range: String,
+
+    /// Version heading (default: the tag at the end of the range, else `Unreleased`)
+    #[arg(long, value_name = "VERSION")]
+    pub release: Option<String>,
+
+    /// Rewrite the entries into user-facing release notes with the AI
+    #[arg(long)]
+    pub rewrite: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    pub format: ChangelogFormat,
+
+    /// Add the section to the top of CHANGELOG.md instead of printing it
+    #[arg(long, conflicts_with = "format")]
+    pub append: bool,
+
+    /// Specify the AI model to use
+    #[arg(short, long, requires = "rewrite")]
+    pub model: Option<String>,
+
+    /// Additional context to guide the AI
+    #[arg(short, long, requires = "rewrite")]
+    pub context: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct PrArgs {
+    /// Branch to compare against (overrides `git.pr_base`)
+    #[arg(short, long, value_name = "BRANCH")]
+    pub base: Option<String>,
+
+    /// Write the title and description to this file instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Specify the AI model to use
+    #[arg(short, long)]
+    pub model: Option<String>,
+
+    /// Additional context to guide the AI
+    #[arg(short, long)]
+    pub context: Option<String>,
+
+    /// Title style (overrides `git.style`)
+    #[arg(long, value_enum)]
+    pub style: Option<MessageStyle>,
+}
+
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct StashArgs {
+    #[command(subcommand)]
+    pub action: Option<StashAction>,
+
+    /// Also stash untracked files
+    #[arg(short = 'u', long)]
+    pub include_untracked: bool,
+
+    /// Specify the AI model to use
+    #[arg(short, long)]
+    pub model: Option<String>,
+
+    /// Additional context to guide the AI
+    #[arg(short, long)]
+    pub context: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StashAction {
+    /// List the stash entries
+    List {
+        /// Suggest descriptions for entries with git's default `WIP on` message
+        #[arg(long)]
+        describe: bool,
+
+        /// Specify the AI model to use
+        #[arg(short, long)]
+        model: Option<String>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct SplitArgs {
+    /// Commit the proposed split without reviewing it
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Specify the AI model to use
+    #[arg(short, long)]
+    pub model: Option<String>,
+
+    /// Additional context to guide the AI
+    #[arg(short, long)]
+    pub context: Option<String>,
+
+    /// Commit message style (overrides `git.style`)
+    #[arg(long, value_enum)]
+    pub style: Option<MessageStyle>,
+}
+
+#[derive(Args, Debug)]
+pub struct SquashArgs {
+    /// Commits to summarise: `base..head`, or `base` for `base..HEAD`
+    pub range: String,
+
+    /// Replace the commits with a single commit using the message
+    #[arg(long)]
+    pub apply: bool,
+
+    /// Apply without asking for confirmation
+    #[arg(short, long, requires = "apply")]
+    pub yes: bool,
+
+    /// Specify the AI model to use
+    #[arg(short, long)]
+    pub model: Option<String>,
+
+    /// Additional context to guide the AI
+    #[arg(short, long)]
+    pub context: Option<String>,
+
+    /// Commit message style (overrides `git.style`)
+    #[arg(long, value_enum)]
+    pub style: Option<MessageStyle>,
+
+    /// Squash even if some commits have already been pushed
+    #[arg(long, requires = "apply")]
+    pub force: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct RewordArgs {
+    /// Commits to reword: `base..tip`, or `base` for `base..HEAD`
+    pub range: String,
+
+    /// Rewrite without reviewing the new messages
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Specify the AI model to use
+    #[arg(short, long)]
+    pub model: Option<String>,
+
+    /// Additional context to guide the AI
+    #[arg(short, long)]
+    pub context: Option<String>,
+
+    /// Commit message style (overrides `git.style`)
+    #[arg(long, value_enum)]
+    pub style: Option<MessageStyle>,
+
+    /// Rewrite even if some commits have already been pushed
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PromptAction {
+    /// Render the prompts that would be sent for the staged changes
+    Render {
+        /// Additional context to guide the AI
+        #[arg(short, long)]
+        context: Option<String>,
+
+        /// Commit message style (overrides `git.style`)
+        #[arg(long, value_enum)]
+        style: Option<MessageStyle>,
+
+        /// Render the prompt for amending HEAD
+        #[arg(long)]
+        amend: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -45,8 +296,20 @@ pub enum ConfigAction {
     /// Set the AI provider
     SetProvider { provider: String },
     /// Set the API key
-    SetApiKey { api_key: String },
-    /// Set the default model
+    SetApiKey {
+        api_key: String,
+        /// Provider the key belongs to (defaults to the active provider)
+        #[arg(long)]
+        provider: Option<String>,
+    },
+    /// Set a custom API base URL
+    SetBaseUrl {
+        base_url: String,
+        /// Provider the URL belongs to (defaults to the active provider)
+        #[arg(long)]
+        provider: Option<String>,
+    },
+    /// Set the default model for the active provider
     SetModel { model: String },
     /// Set temperature (0.0-2.0)
     SetTemperature { temperature: f32 },
@@ -56,68 +319,150 @@ pub enum ConfigAction {
     SetInteractive { interactive: bool },
     /// Enable/disable conventional commits
     SetConventional { conventional: bool },
+    /// Set the commit message style
+    SetStyle {
+        #[arg(value_enum)]
+        style: MessageStyle,
+    },
+    /// Set when to generate a message body
+    SetBody {
+        #[arg(value_enum)]
+        body: BodyMode,
+    },
+    /// Set what to stage automatically when nothing is staged
+    SetAutoStage {
+        #[arg(value_enum)]
+        policy: AutoStage,
+    },
 }
 
 pub mod commit {
     use anyhow::Result;
     use console::style;
-    use dialoguer::{Confirm, Editor};
+    use dialoguer::{Confirm, Editor, MultiSelect, Select};
+    use globset::{Glob, GlobSet, GlobSetBuilder};
     use indicatif::{ProgressBar, ProgressStyle};
 
-    use crate::{ai::AiClient, config::AppConfig, error::AppError, git::GitRepo};
+    use super::CommitArgs;
+    use crate::{
+        ai::AiClient,
+        config::AppConfig,
+        error::AppError,
+        git::{AutoStage, DiffBase, GitRepo, GitStatus, RepoOperation},
+        message::CommitMessage,
+        prompts::{PromptContext, PromptTemplates},
+        style::{MessageStyle, StyleGuide},
+        trailers::Trailers,
+    };
 
     #[allow(clippy::too_many_lines)]
-    pub async fn handle_commit_command(
-        all: bool,
-        yes: bool,
-        model: Option<String>,
-        context: Option<String>,
-    ) -> Result<()> {
-        let config = AppConfig::load()?;
-        let repo = GitRepo::new(".")?;
+    pub async fn handle_commit_command(args: CommitArgs) -> Result<()> {
+        let CommitArgs {
+            all,
+            patch,
+            include,
+            exclude,
+            yes,
+            model,
+            context,
+            style: message_style,
+            body,
+            signoff,
+            co_authors,
+            amend,
+            worktree,
+            force,
+        } = args;
+        let mut config = AppConfig::load()?;
+        let repo = GitRepo::open()?;
 
-        // Check if we're in a git repository
-        if !repo.is_git_repo() {
-            return Err(AppError::NotInGitRepo.into());
+        if message_style.is_some() {
+            config.git.style = message_style;
+        }
+        if let Some(body) = body {
+            config.git.body = body;
+        }
+        let style_guide = StyleGuide::from_config(&config.git)?;
+
+        if repo.has_conflicts()? {
+            return Err(AppError::UnresolvedConflicts.into());
+        }
+
+        if worktree {
+            match suggest_for_worktree(
+                &mut config,
+                &repo,
+                &style_guide,
+                context.as_deref(),
+                model.as_deref(),
+                &co_authors,
+            )
+            .await?
+            {
+                // Plain output so it can be reused once the changes are staged
+                Some(message) => println!("{message}"),
+                None => println!("{}", style("No changes to commit").yellow()),
+            }
+            return Ok(());
+        }
+
+        if amend
+            && !force
+            && let Some(remote_branch) = repo.head_pushed_to()?
+        {
+            return Err(AppError::AlreadyPushed("HEAD".to_string(), remote_branch).into());
         }
 
         // Stage files if requested
-        if all {
+        let filtered = !include.is_empty() || !exclude.is_empty();
+        if filtered {
+            // Untracked files one by one, so globs also match inside new directories
+            let mut changed = repo.get_status()?.modified;
+            changed.extend(repo.untracked_paths()?);
+            let paths = filter_paths(&changed, &include, &exclude)?;
+            if paths.is_empty() {
+                return Err(AppError::NoMatchingPaths.into());
+            }
+            repo.stage_paths(&paths)?;
+            println!(
+                "{}",
+                style(format!("✓ Staged {} matching files", paths.len())).green()
+            );
+        } else if all {
             repo.stage_all()?;
             println!("{}", style("✓ Staged all files").green());
         }
+        if patch {
+            stage_hunks_interactively(&repo)?;
+        }
 
-        // Check for staged changes
+        // Check for staged changes; concluding a merge or amending may legitimately have none
+        let operation = repo.operation();
+        let diff_base = if amend {
+            DiffBase::HeadParent
+        } else {
+            DiffBase::Head
+        };
         let status = repo.get_status()?;
-        if status.staged.is_empty() {
-            if status.modified.is_empty() && status.untracked.is_empty() {
+        if status.staged.is_empty() && !amend && operation != Some(RepoOperation::Merge) {
+            // Filters decide what to stage, so never fall back to `git.auto_stage`
+            if patch || filtered || (status.modified.is_empty() && status.untracked.is_empty()) {
                 println!("{}", style("No changes to commit").yellow());
                 return Ok(());
             }
 
-            // Prompt to stage files
-            if !status.modified.is_empty() {
-                let should_stage = Confirm::new()
-                    .with_prompt("Stage modified files?")
-                    .default(true)
-                    .interact()?;
-
-                if should_stage {
+            // Stage according to `git.auto_stage`, or ask which files to stage
+            match config.git.auto_stage {
+                AutoStage::All => {
+                    repo.stage_all()?;
+                    println!("{}", style("✓ Staged all files").green());
+                }
+                AutoStage::Modified => {
                     repo.stage_modified()?;
                     println!("{}", style("✓ Staged modified files").green());
                 }
-            }
-
-            if !status.untracked.is_empty() {
-                let should_stage = Confirm::new()
-                    .with_prompt("Stage untracked files?")
-                    .default(false)
-                    .interact()?;
-
-                if should_stage {
-                    repo.stage_untracked()?;
-                    println!("{}", style("✓ Staged untracked files").green());
-                }
+                AutoStage::None if config.ui.interactive => pick_files(&repo, &status)?,
+                AutoStage::None => confirm_staging(&repo, &status)?,
             }
 
             // Refresh status
@@ -131,36 +476,81 @@ pub mod commit {
         // Show diff if configured
         if config.ui.show_diff {
             println!("\n{}", style("Staged changes:").bold());
-            let diff = repo.get_staged_diff()?;
+            let diff = repo.get_index_diff(diff_base)?;
             println!("{diff}");
         }
 
-        // Generate commit message
-        let pb = ProgressBar::new_spinner();
-        pb.set_style(
-            ProgressStyle::default_spinner()
-                .template("{spinner:.green} {msg}")
-                .expect("Failed to create progress bar template"),
-        );
-        pb.set_message("Generating commit message...");
-        pb.enable_steady_tick(std::time::Duration::from_millis(100));
-
-        let ai_client = AiClient::new(&config);
-        let diff = repo.get_staged_diff()?;
-        let status_output = repo.get_status_porcelain()?;
-
-        let commit_message = ai_client
-            .generate_commit_message(&diff, &status_output, context.as_deref(), model.as_deref())
-            .await?;
+        let canned = if amend {
+            None
+        } else {
+            canned_message(&repo, operation, style_guide.style)?
+        };
+        let is_canned = canned.is_some();
+        // Merges and reverts only get identity trailers, not issue or breaking-change ones
+        let mut trailers = if is_canned {
+            Trailers::new(&config.git, None, "", &co_authors)?
+        } else {
+            Trailers::new(
+                &config.git,
+                repo.current_branch()?.as_deref(),
+                &repo.get_index_diff(diff_base)?,
+                &co_authors,
+            )?
+        };
+        if signoff || config.git.signoff {
+            trailers = trailers.with_signoff(repo.signature_identity()?);
+        }
 
-        pb.finish_and_clear();
+        let mut commit_message = match canned {
+            Some(message) => message,
+            None => {
+                // Resolve credentials before the spinner so a passphrase prompt stays readable
+                config.resolve_api_key()?;
+                let prompt_context =
+                    PromptContext::from_repo(&repo, &config, context.as_deref(), diff_base)?;
+                generate_message(
+                    &config,
+                    &repo,
+                    &style_guide,
+                    &prompt_context,
+                    model.as_deref(),
+                    "Generating commit message...",
+                )
+                .await?
+            }
+        };
+        trailers.apply(&mut commit_message, style_guide.style);
+        if amend {
+            commit_message.keep_footers(&CommitMessage::parse(&repo.head_message()?));
+        }
+        let write_commit = |message: &str| {
+            if amend {
+                repo.amend(message)
+            } else {
+                repo.commit(message)
+            }
+        };
 
         println!("\n{}", style("Generated commit message:").bold());
         println!("{}", style(&commit_message).cyan());
 
+        // Git's own merge and revert messages are exempt from the style rules
+        let problems = if is_canned {
+            Vec::new()
+        } else {
+            style_guide.validate(&commit_message)
+        };
+        for problem in problems {
+            println!(
+                "{} {}",
+                style(format!("⚠ Not valid {} style:", style_guide.style)).yellow(),
+                problem
+            );
+        }
+
         // Handle commit confirmation
         if yes {
-            repo.commit(&commit_message)?;
+            write_commit(&commit_message.to_string())?;
             println!("\n{}", style("✓ Committed successfully").green());
         } else if config.ui.interactive {
             let choice = dialoguer::Select::new()
@@ -171,14 +561,16 @@ pub mod commit {
 
             match choice {
                 0 => {
-                    repo.commit(&commit_message)?;
+                    write_commit(&commit_message.to_string())?;
                     println!("\n{}", style("✓ Committed successfully").green());
                 }
                 1 => {
-                    if let Some(edited_message) =
-                        Editor::new().extension(".txt").edit(&commit_message)?
+                    if let Some(edited_message) = Editor::new()
+                        .extension(".txt")
+                        .edit(&commit_message.to_string())?
                     {
-                        repo.commit(&edited_message)?;
+                        let edited_message = CommitMessage::parse(&edited_message);
+                        write_commit(&edited_message.to_string())?;
                         println!(
                             "\n{}",
                             style("✓ Committed successfully with edited message").green()
@@ -199,7 +591,7 @@ pub mod commit {
                 .interact()?;
 
             if should_commit {
-                repo.commit(&commit_message)?;
+                write_commit(&commit_message.to_string())?;
                 println!("\n{}", style("✓ Committed successfully").green());
             } else {
                 println!("{}", style("Commit cancelled").yellow());
@@ -208,6 +600,1070 @@ pub mod commit {
 
         Ok(())
     }
+
+    /// Generate a message for the uncommitted changes, or `None` when there are none.
+    async fn suggest_for_worktree(
+        config: &mut AppConfig,
+        repo: &GitRepo,
+        style_guide: &StyleGuide,
+        context: Option<&str>,
+        model: Option<&str>,
+        co_authors: &[String],
+    ) -> Result<Option<CommitMessage>> {
+        if repo.worktree_changed_paths(true)?.is_empty() {
+            return Ok(None);
+        }
+
+        config.resolve_api_key()?;
+        let prompt_context = PromptContext::for_worktree(repo, config, context, true)?;
+        let mut message = generate_message(
+            config,
+            repo,
+            style_guide,
+            &prompt_context,
+            model,
+            "Generating message for the working tree...",
+        )
+        .await?;
+        Trailers::new(
+            &config.git,
+            prompt_context.branch.as_deref(),
+            &prompt_context.diff,
+            co_authors,
+        )?
+        .apply(&mut message, style_guide.style);
+
+        Ok(Some(message))
+    }
+
+    /// Changed files matching any `include` glob (all when empty) and no `exclude` glob.
+    fn filter_paths(
+        changed: &[String],
+        include: &[String],
+        exclude: &[String],
+    ) -> Result<Vec<String>> {
+        let glob_set = |patterns: &[String]| -> Result<GlobSet> {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in patterns {
+                builder.add(
+                    Glob::new(pattern)
+                        .map_err(|e| AppError::InvalidPathFilter(format!("`{pattern}`: {e}")))?,
+                );
+            }
+            Ok(builder.build()?)
+        };
+        let include_set = glob_set(include)?;
+        let exclude_set = glob_set(exclude)?;
+
+        Ok(changed
+            .iter()
+            .filter(|path| include.is_empty() || include_set.is_match(path))
+            .filter(|path| !exclude_set.is_match(path))
+            .cloned()
+            .collect())
+    }
+
+    /// Ask whether to stage the modified and the untracked files.
+    fn confirm_staging(repo: &GitRepo, status: &GitStatus) -> Result<()> {
+        if !status.modified.is_empty() {
+            let should_stage = Confirm::new()
+                .with_prompt("Stage modified files?")
+                .default(true)
+                .interact()?;
+
+            if should_stage {
+                repo.stage_modified()?;
+                println!("{}", style("✓ Staged modified files").green());
+            }
+        }
+
+        if !status.untracked.is_empty() {
+            let should_stage = Confirm::new()
+                .with_prompt("Stage untracked files?")
+                .default(false)
+                .interact()?;
+
+            if should_stage {
+                repo.stage_untracked()?;
+                println!("{}", style("✓ Staged untracked files").green());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Let the user pick the files to stage, modified files preselected.
+    fn pick_files(repo: &GitRepo, status: &GitStatus) -> Result<()> {
+        let stats = repo.unstaged_stats()?;
+        let paths: Vec<&String> = status.modified.iter().chain(&status.untracked).collect();
+        let items: Vec<String> = paths
+            .iter()
+            .map(|path| {
+                // Untracked directories are listed as `dir/`, their files one by one
+                let (additions, deletions) = stats
+                    .iter()
+                    .filter(|(file, _)| {
+                        file == path || (path.ends_with('/') && file.starts_with(*path))
+                    })
+                    .fold((0, 0), |(a, d), (_, (add, del))| (a + add, d + del));
+                let kind = if status.untracked.contains(path) {
+                    " (new)"
+                } else {
+                    ""
+                };
+                format!(
+                    "{path}{kind}  {} {}",
+                    style(format!("+{additions}")).green(),
+                    style(format!("-{deletions}")).red()
+                )
+            })
+            .collect();
+        let defaults: Vec<bool> = paths
+            .iter()
+            .map(|path| status.modified.contains(path))
+            .collect();
+
+        let selected = MultiSelect::new()
+            .with_prompt("Files to stage (space to toggle, enter to confirm)")
+            .items(&items)
+            .defaults(&defaults)
+            .interact()?;
+        if selected.is_empty() {
+            return Ok(());
+        }
+
+        let chosen: Vec<String> = selected.iter().map(|&i| paths[i].clone()).collect();
+        repo.stage_paths(&chosen)?;
+        println!(
+            "{}",
+            style(format!("✓ Staged {} files", chosen.len())).green()
+        );
+        Ok(())
+    }
+
+    /// Walk through the unstaged hunks like `git add -p` and stage the accepted ones.
+    fn stage_hunks_interactively(repo: &GitRepo) -> Result<()> {
+        // Untracked files are offered hunk by hunk too, as after `git add -N`
+        let untracked = repo.untracked_paths()?;
+        repo.intent_to_add(&untracked)?;
+        let result = pick_hunks(repo);
+        repo.clear_intent_to_add(&untracked)?;
+        result
+    }
+
+    fn pick_hunks(repo: &GitRepo) -> Result<()> {
+        let hunks = repo.unstaged_hunks()?;
+        if hunks.is_empty() {
+            println!("{}", style("No unstaged changes").yellow());
+            return Ok(());
+        }
+
+        let mut files: Vec<(&str, usize)> = Vec::new();
+        for hunk in &hunks {
+            match files.last_mut() {
+                Some((path, count)) if *path == hunk.path => *count += 1,
+                _ => files.push((&hunk.path, 1)),
+            }
+        }
+        println!("\n{}", style("Unstaged changes:").bold());
+        for (path, count) in &files {
+            let noun = if *count == 1 { "hunk" } else { "hunks" };
+            println!("  {path} {}", style(format!("({count} {noun})")).dim());
+        }
+
+        let mut accepted = Vec::new();
+        // A file whose remaining hunks were all accepted or skipped at once
+        let mut decided: Option<(&str, bool)> = None;
+        for (index, hunk) in hunks.iter().enumerate() {
+            if let Some((path, accept)) = decided {
+                if path == hunk.path {
+                    if accept {
+                        accepted.push(hunk.id.clone());
+                    }
+                    continue;
+                }
+                decided = None;
+            }
+
+            println!(
+                "\n{} {} {}",
+                style(format!("[{}/{}]", index + 1, hunks.len())).dim(),
+                style(&hunk.path).bold(),
+                style(&hunk.header).cyan()
+            );
+            for line in hunk.patch.lines() {
+                match line.chars().next() {
+                    Some('+') => println!("{}", style(line).green()),
+                    Some('-') => println!("{}", style(line).red()),
+                    _ => println!("{}", style(line).dim()),
+                }
+            }
+
+            let choice = Select::new()
+                .with_prompt("Stage this hunk?")
+                .items(&[
+                    "Yes",
+                    "No",
+                    "Yes, and the rest of this file",
+                    "No, and skip the rest of this file",
+                    "Done, skip the remaining hunks",
+                ])
+                .default(0)
+                .interact()?;
+            match choice {
+                0 => accepted.push(hunk.id.clone()),
+                1 => {}
+                2 | 3 => {
+                    if choice == 2 {
+                        accepted.push(hunk.id.clone());
+                    }
+                    decided = Some((&hunk.path, choice == 2));
+                }
+                _ => break,
+            }
+        }
+
+        if accepted.is_empty() {
+            println!("{}", style("No hunks staged").yellow());
+            return Ok(());
+        }
+        repo.stage_hunks(&accepted)?;
+        println!(
+            "{}",
+            style(format!(
+                "✓ Staged {} of {} hunks",
+                accepted.len(),
+                hunks.len()
+            ))
+            .green()
+        );
+        Ok(())
+    }
+
+    /// The message git itself would use when concluding a merge or revert.
+    fn canned_message(
+        repo: &GitRepo,
+        operation: Option<RepoOperation>,
+        message_style: MessageStyle,
+    ) -> Result<Option<CommitMessage>> {
+        let message = match operation {
+            Some(RepoOperation::Merge) => repo.merge_message()?.map(|m| CommitMessage::parse(&m)),
+            Some(RepoOperation::Revert) => repo.revert_target()?.map(|(id, subject)| {
+                CommitMessage::revert(&subject, &id, message_style == MessageStyle::Conventional)
+            }),
+            _ => None,
+        };
+
+        if let (Some(operation), Some(_)) = (operation, &message) {
+            println!(
+                "{}",
+                style(format!("✓ Using the standard {operation} message")).green()
+            );
+        }
+        Ok(message)
+    }
+
+    /// Render the prompt for `prompt_context` and ask the model, showing `progress`
+    /// in a spinner. Ctrl-C aborts the request.
+    pub(super) async fn generate_message(
+        config: &AppConfig,
+        repo: &GitRepo,
+        style_guide: &StyleGuide,
+        prompt_context: &PromptContext,
+        model: Option<&str>,
+        progress: &str,
+    ) -> Result<CommitMessage> {
+        let pb = spinner(progress);
+        let ai_client = AiClient::new(config);
+        let prompt =
+            PromptTemplates::load(&config.prompts, repo.workdir())?.render(prompt_context)?;
+
+        // Dropping the request future on Ctrl-C aborts the in-flight HTTP call
+        let commit_message = tokio::select! {
+            result = ai_client.generate_commit_message(&prompt, style_guide, model) => result,
+            _ = tokio::signal::ctrl_c() => Err(AppError::Cancelled.into()),
+        };
+
+        pb.finish_and_clear();
+        commit_message
+    }
+
+    pub(super) fn spinner(progress: &str) -> ProgressBar {
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.green} {msg}")
+                .expect("Failed to create progress bar template"),
+        );
+        pb.set_message(progress.to_string());
+        pb.enable_steady_tick(std::time::Duration::from_millis(100));
+        pb
+    }
+}
+
+pub mod reword {
+    use anyhow::Result;
+    use console::style;
+    use dialoguer::{Confirm, Editor, Select};
+
+    use super::{RewordArgs, commit::generate_message};
+    use crate::{
+        config::AppConfig,
+        error::AppError,
+        git::{BACKUP_REF, CommitInfo, GitRepo},
+        message::CommitMessage,
+        prompts::{PromptContext, PromptConventions},
+        style::StyleGuide,
+        trailers::Trailers,
+    };
+
+    /// Width of the subject columns in the review table.
+    const SUBJECT_WIDTH: usize = 40;
+
+    pub async fn handle_reword_command(args: RewordArgs) -> Result<()> {
+        let mut config = AppConfig::load()?;
+        if args.style.is_some() {
+            config.git.style = args.style;
+        }
+        let style_guide = StyleGuide::from_config(&config.git)?;
+
+        let repo = GitRepo::open()?;
+
+        let commits = repo.commits_in_range(&args.range)?;
+        if commits.is_empty() {
+            println!("{}", style("No commits to reword").yellow());
+            return Ok(());
+        }
+        if !args.force {
+            for commit in &commits {
+                if let Some(remote_branch) = repo.pushed_to(commit.id)? {
+                    return Err(AppError::AlreadyPushed(commit.short_id(), remote_branch).into());
+                }
+            }
+        }
+
+        config.resolve_api_key()?;
+        let branch = repo.current_branch()?;
+        let conventions = PromptConventions::load(&repo, &config)?;
+
+        let total = commits.len();
+        let mut rewrites: Vec<(CommitInfo, Option<CommitMessage>)> = Vec::new();
+        for (index, commit) in commits.into_iter().enumerate() {
+            let prompt_context = PromptContext::for_commit(
+                &repo,
+                &config,
+                &conventions,
+                args.context.as_deref(),
+                &commit,
+            )?;
+            let progress = format!(
+                "Rewording {} ({}/{})...",
+                commit.short_id(),
+                index + 1,
+                total
+            );
+            let mut message = generate_message(
+                &config,
+                &repo,
+                &style_guide,
+                &prompt_context,
+                args.model.as_deref(),
+                &progress,
+            )
+            .await?;
+
+            Trailers::new(&config.git, branch.as_deref(), &prompt_context.diff, &[])?
+                .apply(&mut message, style_guide.style);
+            message.keep_footers(&CommitMessage::parse(&commit.message));
+            rewrites.push((commit, Some(message)));
+        }
+
+        if !args.yes && !review(&mut rewrites, config.ui.interactive)? {
+            println!("{}", style("Reword cancelled").yellow());
+            return Ok(());
+        }
+
+        let messages: Vec<(git2::Oid, String)> = rewrites
+            .iter()
+            .filter_map(|(commit, message)| {
+                message
+                    .as_ref()
+                    .map(|message| (commit.id, message.to_string()))
+            })
+            .collect();
+        if messages.is_empty() {
+            println!("{}", style("No messages changed").yellow());
+            return Ok(());
+        }
+
+        repo.reword_commits(&messages)?;
+        println!(
+            "\n{}",
+            style(format!("✓ Reworded {} commits", messages.len())).green()
+        );
+        println!(
+            "{}",
+            style(format!("Undo with: git reset --soft {BACKUP_REF}")).dim()
+        );
+
+        Ok(())
+    }
+
+    fn print_table(rewrites: &[(CommitInfo, Option<CommitMessage>)]) {
+        println!(
+            "\n{}",
+            style(format!(
+                "{:>3}  {:<7}  {:<SUBJECT_WIDTH$}  {}",
+                "#", "Commit", "Old subject", "New subject"
+            ))
+            .bold()
+        );
+        for (index, (commit, message)) in rewrites.iter().enumerate() {
+            let new_subject = message.as_ref().map_or_else(
+                || style("(unchanged)".to_string()).dim(),
+                |message| style(message.subject.clone()).cyan(),
+            );
+            println!(
+                "{:>3}  {}  {:<SUBJECT_WIDTH$}  {}",
+                index + 1,
+                style(commit.short_id()).yellow(),
+                truncate(commit.summary(), SUBJECT_WIDTH),
+                new_subject
+            );
+        }
+        println!();
+    }
+
+    /// Let the user edit or drop individual messages; returns whether to rewrite.
+    fn review(
+        rewrites: &mut [(CommitInfo, Option<CommitMessage>)],
+        interactive: bool,
+    ) -> Result<bool> {
+        loop {
+            print_table(rewrites);
+
+            if !interactive {
+                return Ok(Confirm::new()
+                    .with_prompt(format!("Rewrite {} commits?", rewrites.len()))
+                    .default(true)
+                    .interact()?);
+            }
+
+            let choice = Select::new()
+                .with_prompt("What would you like to do?")
+                .items(&[
+                    "Rewrite history",
+                    "Edit a message",
+                    "Keep a commit's original message",
+                    "Cancel",
+                ])
+                .default(0)
+                .interact()?;
+
+            match choice {
+                0 => return Ok(true),
+                1 | 2 => {
+                    let items: Vec<String> = rewrites
+                        .iter()
+                        .map(|(commit, _)| format!("{} {}", commit.short_id(), commit.summary()))
+                        .collect();
+                    let selected = Select::new()
+                        .with_prompt("Which commit?")
+                        .items(&items)
+                        .default(0)
+                        .interact()?;
+                    let (commit, message) = &mut rewrites[selected];
+
+                    if choice == 2 {
+                        *message = None;
+                        continue;
+                    }
+                    let current = message
+                        .as_ref()
+                        .map_or_else(|| commit.message.clone(), ToString::to_string);
+                    if let Some(edited) = Editor::new().extension(".txt").edit(&current)? {
+                        *message = Some(CommitMessage::parse(&edited));
+                    }
+                }
+                _ => return Ok(false),
+            }
+        }
+    }
+
+    fn truncate(text: &str, width: usize) -> String {
+        if text.chars().count() <= width {
+            return text.to_string();
+        }
+        let mut truncated: String = text.chars().take(width - 1).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
+pub mod squash {
+    use anyhow::Result;
+    use console::style;
+    use dialoguer::Confirm;
+
+    use super::{SquashArgs, commit::generate_message};
+    use crate::{
+        config::AppConfig,
+        error::AppError,
+        git::{BACKUP_REF, GitRepo},
+        message::{BodyMode, CommitMessage},
+        prompts::PromptContext,
+        style::StyleGuide,
+        trailers::Trailers,
+    };
+
+    pub async fn handle_squash_command(args: SquashArgs) -> Result<()> {
+        let mut config = AppConfig::load()?;
+        if args.style.is_some() {
+            config.git.style = args.style;
+        }
+        config.git.body = BodyMode::Always;
+        let style_guide = StyleGuide::from_config(&config.git)?;
+
+        let repo = GitRepo::open()?;
+
+        let commits = repo.commits_in_range(&args.range)?;
+        let (Some(first), Some(last)) = (commits.first(), commits.last()) else {
+            println!("{}", style("No commits to squash").yellow());
+            return Ok(());
+        };
+
+        if args.apply {
+            if last.id != repo.head_id()? {
+                return Err(AppError::InvalidRange(format!(
+                    "{} must end at HEAD to be squashed",
+                    args.range
+                ))
+                .into());
+            }
+            if !args.force
+                && let Some(remote_branch) = repo.pushed_to(first.id)?
+            {
+                return Err(AppError::AlreadyPushed(first.short_id(), remote_branch).into());
+            }
+        }
+
+        config.resolve_api_key()?;
+        let prompt_context =
+            PromptContext::for_squash(&repo, &config, args.context.as_deref(), &commits)?;
+        let mut message = generate_message(
+            &config,
+            &repo,
+            &style_guide,
+            &prompt_context,
+            args.model.as_deref(),
+            &format!("Summarising {} commits...", commits.len()),
+        )
+        .await?;
+
+        Trailers::new(
+            &config.git,
+            repo.current_branch()?.as_deref(),
+            &prompt_context.diff,
+            &[],
+        )?
+        .apply(&mut message, style_guide.style);
+        for commit in &commits {
+            message.keep_footers(&CommitMessage::parse(&commit.message));
+        }
+
+        if !args.apply {
+            // Plain output so it can be piped into `git commit -F -`
+            println!("{message}");
+            return Ok(());
+        }
+
+        println!("\n{}", style("Squash commit message:").bold());
+        println!("{}", style(&message).cyan());
+
+        let confirmed = args.yes
+            || Confirm::new()
+                .with_prompt(format!("Squash {} commits into one?", commits.len()))
+                .default(true)
+                .interact()?;
+        if !confirmed {
+            println!("{}", style("Squash cancelled").yellow());
+            return Ok(());
+        }
+
+        repo.squash_commits(repo.parent_id(first.id)?, &message.to_string())?;
+        println!(
+            "\n{}",
+            style(format!("✓ Squashed {} commits", commits.len())).green()
+        );
+        println!(
+            "{}",
+            style(format!("Undo with: git reset --soft {BACKUP_REF}")).dim()
+        );
+
+        Ok(())
+    }
+}
+
+pub mod split {
+    use anyhow::Result;
+    use console::style;
+    use dialoguer::{Editor, Select};
+
+    use super::{SplitArgs, commit::spinner};
+    use crate::{
+        ai::AiClient,
+        config::AppConfig,
+        error::AppError,
+        git::{GitRepo, Hunk, short_id},
+        message::CommitMessage,
+        prompts::{PromptContext, PromptTemplates},
+        split::SplitPlan,
+        style::StyleGuide,
+        trailers::Trailers,
+    };
+
+    pub async fn handle_split_command(args: SplitArgs) -> Result<()> {
+        let mut config = AppConfig::load()?;
+        if args.style.is_some() {
+            config.git.style = args.style;
+        }
+        let style_guide = StyleGuide::from_config(&config.git)?;
+
+        let repo = GitRepo::open()?;
+        if repo.has_conflicts()? {
+            return Err(AppError::UnresolvedConflicts.into());
+        }
+
+        let hunks = repo.staged_hunks()?;
+        if hunks.is_empty() {
+            println!("{}", style("No staged changes to split").yellow());
+            return Ok(());
+        }
+        if hunks.len() == 1 {
+            println!(
+                "{}",
+                style("Only one hunk is staged; commit it with `ai-commit`").yellow()
+            );
+            return Ok(());
+        }
+
+        config.resolve_api_key()?;
+        let prompt_context =
+            PromptContext::for_split(&repo, &config, args.context.as_deref(), hunks.clone())?;
+        let prompt = PromptTemplates::load(&config.prompts, repo.workdir())?
+            .render_split(&prompt_context)?;
+
+        let pb = spinner(&format!("Grouping {} hunks into commits...", hunks.len()));
+        let ai_client = AiClient::new(&config);
+        let generate =
+            ai_client.generate(&prompt, &style_guide, args.model.as_deref(), |response| {
+                let plan = SplitPlan::parse(response, &hunks, &style_guide)?;
+                let problems = plan.problems(&style_guide);
+                Ok((plan, problems))
+            });
+        let plan = tokio::select! {
+            result = generate => result,
+            _ = tokio::signal::ctrl_c() => Err(AppError::Cancelled.into()),
+        };
+        pb.finish_and_clear();
+
+        let mut plan = plan?;
+        let branch = repo.current_branch()?;
+        for commit in &mut plan.commits {
+            let diff: String = group_hunks(&hunks, &commit.hunks)
+                .map(Hunk::file_patch)
+                .collect();
+            Trailers::new(&config.git, branch.as_deref(), &diff, &[])?
+                .apply(&mut commit.message, style_guide.style);
+        }
+
+        if !args.yes && !review(&mut plan, &hunks, &style_guide)? {
+            println!("{}", style("Split cancelled").yellow());
+            return Ok(());
+        }
+
+        let groups: Vec<(Vec<String>, String)> = plan
+            .commits
+            .iter()
+            .map(|commit| (commit.hunks.clone(), commit.message.to_string()))
+            .collect();
+        let commit_ids = repo.commit_hunk_groups(&hunks, &groups)?;
+
+        println!(
+            "\n{}",
+            style(format!("✓ Created {} commits", commit_ids.len())).green()
+        );
+        for (id, commit) in commit_ids.iter().zip(&plan.commits) {
+            println!(
+                "  {} {}",
+                style(short_id(*id)).yellow(),
+                commit.message.subject
+            );
+        }
+
+        Ok(())
+    }
+
+    fn group_hunks<'a>(hunks: &'a [Hunk], ids: &'a [String]) -> impl Iterator<Item = &'a Hunk> {
+        hunks.iter().filter(|hunk| ids.contains(&hunk.id))
+    }
+
+    fn print_plan(plan: &SplitPlan, hunks: &[Hunk], style_guide: &StyleGuide) {
+        for (index, commit) in plan.commits.iter().enumerate() {
+            println!(
+                "\n{}",
+                style(format!("Commit {}/{}", index + 1, plan.commits.len())).bold()
+            );
+            println!("{}", style(&commit.message).cyan());
+            for problem in style_guide.validate(&commit.message) {
+                println!("{}", style(format!("  ⚠ {problem}")).yellow());
+            }
+            for hunk in group_hunks(hunks, &commit.hunks) {
+                println!(
+                    "  {} {} {}",
+                    style(&hunk.id).dim(),
+                    hunk.path,
+                    style(&hunk.header).dim()
+                );
+            }
+        }
+        println!();
+    }
+
+    /// Let the user edit the planned messages; returns whether to commit.
+    fn review(plan: &mut SplitPlan, hunks: &[Hunk], style_guide: &StyleGuide) -> Result<bool> {
+        loop {
+            print_plan(plan, hunks, style_guide);
+
+            let choice = Select::new()
+                .with_prompt("What would you like to do?")
+                .items(&[
+                    format!("Create {} commits", plan.commits.len()),
+                    "Edit a message".to_string(),
+                    "Cancel".to_string(),
+                ])
+                .default(0)
+                .interact()?;
+
+            match choice {
+                0 => return Ok(true),
+                1 => {
+                    let items: Vec<String> = plan
+                        .commits
+                        .iter()
+                        .enumerate()
+                        .map(|(index, commit)| format!("{}. {}", index + 1, commit.message.subject))
+                        .collect();
+                    let selected = Select::new()
+                        .with_prompt("Which commit?")
+                        .items(&items)
+                        .default(0)
+                        .interact()?;
+                    let message = &mut plan.commits[selected].message;
+                    if let Some(edited) =
+                        Editor::new().extension(".txt").edit(&message.to_string())?
+                    {
+                        *message = CommitMessage::parse(&edited);
+                    }
+                }
+                _ => return Ok(false),
+            }
+        }
+    }
+}
+
+pub mod stash {
+    use anyhow::Result;
+    use console::style;
+
+    use super::{StashAction, StashArgs, commit::generate_message};
+    use crate::{
+        config::AppConfig,
+        git::{CommitInfo, GitRepo},
+        message::BodyMode,
+        prompts::{PromptContext, PromptConventions},
+        style::StyleGuide,
+    };
+
+    pub async fn handle_stash_command(args: StashArgs) -> Result<()> {
+        match args.action {
+            Some(StashAction::List { describe, model }) => list(describe, model.as_deref()).await,
+            None => push(&args).await,
+        }
+    }
+
+    async fn push(args: &StashArgs) -> Result<()> {
+        let mut config = AppConfig::load()?;
+        // Stash entries are listed one line each
+        config.git.body = BodyMode::Off;
+        let style_guide = StyleGuide::from_config(&config.git)?;
+
+        let mut repo = GitRepo::open()?;
+        if repo
+            .worktree_changed_paths(args.include_untracked)?
+            .is_empty()
+        {
+            println!("{}", style("No local changes to stash").yellow());
+            return Ok(());
+        }
+
+        config.resolve_api_key()?;
+        let prompt_context = PromptContext::for_worktree(
+            &repo,
+            &config,
+            args.context.as_deref(),
+            args.include_untracked,
+        )?;
+        let message = generate_message(
+            &config,
+            &repo,
+            &style_guide,
+            &prompt_context,
+            args.model.as_deref(),
+            "Describing the changes...",
+        )
+        .await?;
+
+        repo.stash_save(&message.subject, args.include_untracked)?;
+        println!(
+            "{} {}",
+            style("✓ Saved stash@{0}:").green(),
+            style(&message.subject).cyan()
+        );
+
+        Ok(())
+    }
+
+    async fn list(describe: bool, model: Option<&str>) -> Result<()> {
+        let mut config = AppConfig::load()?;
+        config.git.body = BodyMode::Off;
+        let style_guide = StyleGuide::from_config(&config.git)?;
+
+        let repo = GitRepo::open()?;
+        let stashes = repo.stashes()?;
+        if stashes.is_empty() {
+            println!("{}", style("No stash entries").yellow());
+            return Ok(());
+        }
+        let conventions = if describe && stashes.iter().any(|entry| entry.is_default_message()) {
+            config.resolve_api_key()?;
+            Some(PromptConventions::load(&repo, &config)?)
+        } else {
+            None
+        };
+
+        for entry in &stashes {
+            println!(
+                "{} {}",
+                style(format!("stash@{{{}}}:", entry.index)).yellow(),
+                entry.message
+            );
+            let Some(conventions) = conventions.as_ref().filter(|_| entry.is_default_message())
+            else {
+                continue;
+            };
+
+            // The stash commit's first parent is the commit it was made on
+            let commit = CommitInfo {
+                id: entry.id,
+                message: entry.message.clone(),
+            };
+            let mut prompt_context =
+                PromptContext::for_commit(&repo, &config, conventions, None, &commit)?;
+            prompt_context.previous_message = None;
+            let message = generate_message(
+                &config,
+                &repo,
+                &style_guide,
+                &prompt_context,
+                model,
+                &format!("Describing stash@{{{}}}...", entry.index),
+            )
+            .await?;
+            println!("  {} {}", style("↳").dim(), style(&message.subject).cyan());
+        }
+
+        Ok(())
+    }
+}
+
+pub mod pr {
+    use anyhow::Result;
+    use console::style;
+
+    use super::{PrArgs, commit::spinner};
+    use crate::{
+        ai::AiClient,
+        config::AppConfig,
+        error::AppError,
+        git::GitRepo,
+        pr::PullRequest,
+        prompts::{PromptContext, PromptTemplates},
+        style::StyleGuide,
+    };
+
+    pub async fn handle_pr_command(args: PrArgs) -> Result<()> {
+        let mut config = AppConfig::load()?;
+        if args.style.is_some() {
+            config.git.style = args.style;
+        }
+        let style_guide = StyleGuide::from_config(&config.git)?;
+
+        let repo = GitRepo::open()?;
+        let Some(base) = args
+            .base
+            .or_else(|| config.git.pr_base.clone())
+            .or_else(|| repo.default_base())
+        else {
+            return Err(AppError::NoBaseBranch.into());
+        };
+
+        let (merge_base, commits) = repo.branch_commits(&base)?;
+        if commits.is_empty() {
+            eprintln!(
+                "{}",
+                style(format!("No commits on this branch since {base}")).yellow()
+            );
+            return Ok(());
+        }
+
+        config.resolve_api_key()?;
+        let prompt_context = PromptContext::for_pull_request(
+            &repo,
+            &config,
+            args.context.as_deref(),
+            &base,
+            merge_base,
+            &commits,
+        )?;
+        let prompt =
+            PromptTemplates::load(&config.prompts, repo.workdir())?.render_pr(&prompt_context)?;
+
+        let pb = spinner(&format!(
+            "Describing {} commits against {base}...",
+            commits.len()
+        ));
+        let ai_client = AiClient::new(&config);
+        let generate =
+            ai_client.generate(&prompt, &style_guide, args.model.as_deref(), |response| {
+                let pull_request = PullRequest::parse(response, &style_guide)?;
+                let problems = pull_request.problems(&style_guide);
+                Ok((pull_request, problems))
+            });
+        let pull_request = tokio::select! {
+            result = generate => result,
+            _ = tokio::signal::ctrl_c() => Err(AppError::Cancelled.into()),
+        };
+        pb.finish_and_clear();
+
+        let pull_request = pull_request?;
+        for problem in pull_request.problems(&style_guide) {
+            eprintln!("{}", style(format!("⚠ {problem}")).yellow());
+        }
+        match args.output {
+            Some(path) => {
+                std::fs::write(&path, format!("{pull_request}\n"))?;
+                eprintln!(
+                    "{} {}",
+                    style("✓ Wrote pull request description to").green(),
+                    path.display()
+                );
+            }
+            // Plain output so it can be piped into other tools
+            None => println!("{pull_request}"),
+        }
+
+        Ok(())
+    }
+}
+
+pub mod changelog {
+    use anyhow::Result;
+    use console::style;
+
+    use super::{ChangelogArgs, commit::spinner};
+    use crate::{
+        ai::AiClient,
+        changelog::{ChangelogFormat, Release, UNRELEASED},
+        config::AppConfig,
+        error::AppError,
+        git::GitRepo,
+        prompts::{PromptContext, PromptTemplates},
+    };
+
+    const CHANGELOG_FILE: &str = "CHANGELOG.md";
+
+    pub async fn handle_changelog_command(args: ChangelogArgs) -> Result<()> {
+        let mut config = AppConfig::load()?;
+        let repo = GitRepo::open()?;
+
+        let (tip, commits) = repo.log_range(&args.range)?;
+        let tag = repo.tag_at(tip)?;
+        let version = match (args.release, &tag) {
+            (Some(release), _) => release,
+            // `v1.2.0` is listed as `1.2.0`
+            (None, Some(tag)) => tag.strip_prefix('v').unwrap_or(tag).to_string(),
+            (None, None) => UNRELEASED.to_string(),
+        };
+        let date = (version != UNRELEASED)
+            .then(|| repo.commit_date(tip))
+            .transpose()?;
+        let mut release = Release::from_commits(version, date, &commits);
+
+        if args.rewrite && !release.is_empty() {
+            config.resolve_api_key()?;
+            let prompt_context = PromptContext::for_changelog(
+                &repo,
+                &config,
+                args.context.as_deref(),
+                release.entries(),
+            )?;
+            let prompt = PromptTemplates::load(&config.prompts, repo.workdir())?
+                .render_changelog(&prompt_context)?;
+
+            let pb = spinner(&format!(
+                "Writing release notes for {} commits...",
+                prompt_context.changelog.len()
+            ));
+            let ai_client = AiClient::new(&config);
+            let response = tokio::select! {
+                result = ai_client.complete(&prompt, args.model.as_deref()) => result,
+                _ = tokio::signal::ctrl_c() => Err(AppError::Cancelled.into()),
+            };
+            pb.finish_and_clear();
+            release.apply_notes(&response?)?;
+        }
+
+        if release.is_empty() {
+            eprintln!(
+                "{}",
+                style(format!("No user-facing changes in {}", args.range)).yellow()
+            );
+            return Ok(());
+        }
+
+        if args.append {
+            let path = repo
+                .workdir()
+                .ok_or(AppError::NotInGitRepo)?
+                .join(CHANGELOG_FILE);
+            release.prepend_to(&path)?;
+            eprintln!(
+                "{} {} {}",
+                style("✓ Added").green(),
+                style(&release.version).cyan(),
+                style(format!("to {CHANGELOG_FILE}")).green()
+            );
+            return Ok(());
+        }
+
+        match args.format {
+            ChangelogFormat::Markdown => print!("{}", release.to_markdown()),
+            ChangelogFormat::Json => println!("{}", release.to_json()?),
+        }
+
+        Ok(())
+    }
 }
 
 pub mod config {
@@ -215,7 +1671,11 @@ pub mod config {
     use console::style;
 
     use super::ConfigAction;
-    use crate::{config::AppConfig, error::AppError};
+    use crate::{
+        config::{self, AppConfig},
+        error::AppError,
+        secrets::{self, SecretStore},
+    };
 
     pub fn handle_config_command(action: ConfigAction) -> Result<()> {
         let mut config = AppConfig::load().unwrap_or_default();
@@ -224,15 +1684,75 @@ pub mod config {
             ConfigAction::Show => {
                 println!("{}", style("Current configuration:").bold());
                 println!("Provider: {}", style(&config.ai.provider).cyan());
-                println!("Model: {}", style(&config.ai.model).cyan());
+                println!("Model: {}", style(config.model()).cyan());
+                let provider = config.provider().cloned().unwrap_or_default();
+                let api_key = match provider.api_key.as_deref() {
+                    Some(api_key) if config::env_reference(api_key).is_some() => {
+                        api_key.to_string()
+                    }
+                    Some(api_key) => secrets::mask(api_key),
+                    None => match SecretStore::new(config.ai.secret_backend)
+                        .get_api_key(&config.ai.provider)
+                    {
+                        Ok(Some(api_key)) => secrets::mask(&api_key),
+                        Ok(None) => "(not set)".to_string(),
+                        Err(_) => "(unavailable)".to_string(),
+                    },
+                };
+                println!("API key: {}", style(api_key).cyan());
+                if let Some(base_url) = provider.base_url.as_deref() {
+                    println!("Base URL: {}", style(base_url).cyan());
+                }
+                println!("Secret backend: {}", style(config.ai.secret_backend).cyan());
                 println!("Temperature: {}", style(config.ai.temperature).cyan());
                 println!("Max tokens: {}", style(config.ai.max_tokens).cyan());
+                println!(
+                    "Timeout: {}s (connect {}s)",
+                    style(config.ai.timeout_secs).cyan(),
+                    style(config.ai.connect_timeout_secs).cyan()
+                );
+                println!(
+                    "Retries: {} (backoff {}ms)",
+                    style(config.ai.max_retries).cyan(),
+                    style(config.ai.retry_backoff_ms).cyan()
+                );
                 println!("Interactive: {}", style(config.ui.interactive).cyan());
                 println!(
                     "Conventional commits: {}",
                     style(config.git.conventional_commits).cyan()
                 );
+                println!(
+                    "Message style: {}",
+                    style(config.git.message_style()).cyan()
+                );
+                println!(
+                    "Max subject length: {}",
+                    style(config.git.max_subject_length).cyan()
+                );
+                println!(
+                    "On invalid message: {}",
+                    style(config.git.on_invalid).cyan()
+                );
+                println!(
+                    "Message body: {} (auto threshold {} lines)",
+                    style(config.git.body).cyan(),
+                    style(config.git.body_threshold).cyan()
+                );
+                println!(
+                    "Issue trailer: {} (from branch: {})",
+                    style(&config.git.issue_trailer).cyan(),
+                    style(config.git.issue_from_branch).cyan()
+                );
+                println!(
+                    "Detect breaking changes: {}",
+                    style(config.git.detect_breaking).cyan()
+                );
+                println!("Sign off: {}", style(config.git.signoff).cyan());
                 println!("Auto stage: {}", style(config.git.auto_stage).cyan());
+                if !config.providers.is_empty() {
+                    let names: Vec<&str> = config.providers.keys().map(String::as_str).collect();
+                    println!("Configured providers: {}", style(names.join(", ")).cyan());
+                }
                 println!(
                     "Config file: {}",
                     style(AppConfig::get_config_path().display()).dim()
@@ -240,20 +1760,55 @@ pub mod config {
             }
             ConfigAction::SetProvider { provider } => {
                 config.ai.provider.clone_from(&provider);
+                // Pin the provider's default model so the previous provider's
+                // `ai.model` is not carried over
+                let model = config.model().to_string();
+                if model != config.ai.model {
+                    config.provider_mut(&provider).model = Some(model.clone());
+                }
                 config.save()?;
                 println!(
                     "{} {}",
                     style("✓ Set provider to:").green(),
                     style(provider).cyan()
                 );
+                println!("Model: {}", style(model).cyan());
+            }
+            ConfigAction::SetApiKey { api_key, provider } => {
+                let provider = provider.unwrap_or_else(|| config.ai.provider.clone());
+                let backend =
+                    SecretStore::new(config.ai.secret_backend).set_api_key(&provider, &api_key)?;
+
+                // Drop any plain-text key left over in the config file, but keep a
+                // deliberate `${ENV_VAR}` reference
+                if let Some(entry) = config.providers.get_mut(&provider)
+                    && entry
+                        .api_key
+                        .take_if(|api_key| config::env_reference(api_key).is_none())
+                        .is_some()
+                {
+                    config.save()?;
+                }
+
+                println!(
+                    "{} {}",
+                    style(format!("✓ API key for {provider} stored in:")).green(),
+                    style(backend).cyan()
+                );
             }
-            ConfigAction::SetApiKey { api_key } => {
-                config.ai.api_key = Some(api_key);
+            ConfigAction::SetBaseUrl { base_url, provider } => {
+                let provider = provider.unwrap_or_else(|| config.ai.provider.clone());
+                config.provider_mut(&provider).base_url = Some(base_url.clone());
                 config.save()?;
-                println!("{}", style("✓ API key updated").green());
+                println!(
+                    "{} {}",
+                    style(format!("✓ Set {provider} base URL to:")).green(),
+                    style(base_url).cyan()
+                );
             }
             ConfigAction::SetModel { model } => {
-                config.ai.model.clone_from(&model);
+                let provider = config.ai.provider.clone();
+                config.provider_mut(&provider).model = Some(model.clone());
                 config.save()?;
                 println!(
                     "{} {}",
@@ -300,6 +1855,35 @@ pub mod config {
                     style(conventional).cyan()
                 );
             }
+            ConfigAction::SetStyle {
+                style: message_style,
+            } => {
+                config.git.style = Some(message_style);
+                config.save()?;
+                println!(
+                    "{} {}",
+                    style("✓ Set message style to:").green(),
+                    style(message_style).cyan()
+                );
+            }
+            ConfigAction::SetBody { body } => {
+                config.git.body = body;
+                config.save()?;
+                println!(
+                    "{} {}",
+                    style("✓ Set message body mode to:").green(),
+                    style(body).cyan()
+                );
+            }
+            ConfigAction::SetAutoStage { policy } => {
+                config.git.auto_stage = policy;
+                config.save()?;
+                println!(
+                    "{} {}",
+                    style("✓ Set auto stage to:").green(),
+                    style(policy).cyan()
+                );
+            }
         }
 
         Ok(())
@@ -321,7 +1905,7 @@ pub mod models {
         let models = ai_client.list_models()?;
 
         for model in models {
-            if model == config.ai.model {
+            if model == config.model() {
                 println!("  {} {}", style("●").green(), style(&model).cyan().bold());
             } else {
                 println!("  {} {}", style("○").dim(), style(&model).cyan());
@@ -332,6 +1916,58 @@ pub mod models {
     }
 }
 
+pub mod prompt {
+    use anyhow::Result;
+    use console::style;
+
+    use super::PromptAction;
+    use crate::{
+        config::AppConfig,
+        git::{DiffBase, GitRepo},
+        prompts::{PromptContext, PromptTemplates},
+    };
+
+    pub fn handle_prompt_command(action: PromptAction) -> Result<()> {
+        match action {
+            PromptAction::Render {
+                context,
+                style: message_style,
+                amend,
+            } => {
+                let mut config = AppConfig::load()?;
+                if message_style.is_some() {
+                    config.git.style = message_style;
+                }
+                let repo = GitRepo::open()?;
+
+                let templates = PromptTemplates::load(&config.prompts, repo.workdir())?;
+                let diff_base = if amend {
+                    DiffBase::HeadParent
+                } else {
+                    DiffBase::Head
+                };
+                let prompt_context =
+                    PromptContext::from_repo(&repo, &config, context.as_deref(), diff_base)?;
+                let prompt = templates.render(&prompt_context)?;
+
+                for source in &templates.sources {
+                    println!(
+                        "{} {}",
+                        style("Template:").dim(),
+                        style(source.display()).dim()
+                    );
+                }
+                println!("{}", style("System prompt:").bold());
+                println!("{}\n", prompt.system);
+                println!("{}", style("User prompt:").bold());
+                println!("{}", prompt.user);
+            }
+        }
+
+        Ok(())
+    }
+}
+
 pub mod auth {
     use std::process::Command;
 
@@ -1,10 +1,19 @@
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 
 use anyhow::Result;
 use dirs::config_dir;
+use genai::adapter::AdapterKind;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
-use crate::error::AppError;
+use crate::{
+    error::AppError,
+    git::AutoStage,
+    message::BodyMode,
+    scopes::ScopeRule,
+    secrets::{SecretBackend, SecretStore},
+    style::{CONVENTIONAL_TYPES, InvalidMessagePolicy, MessageStyle},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
@@ -12,22 +21,170 @@ pub struct AppConfig {
     pub git: GitConfig,
     pub ui: UiConfig,
     pub prompts: PromptsConfig,
+    #[serde(default)]
+    pub providers: BTreeMap<String, ProviderConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiConfig {
     pub provider: String,
+    /// Fallback model for providers without their own `model` entry.
     pub model: String,
+    /// Legacy single key, moved into `[providers.<provider>]` on load.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub api_key: Option<String>,
+    #[serde(default)]
+    pub secret_backend: SecretBackend,
     pub temperature: f32,
     pub max_tokens: u32,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+}
+
+const fn default_timeout_secs() -> u64 {
+    60
+}
+
+const fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+const fn default_max_retries() -> u32 {
+    3
+}
+
+const fn default_retry_backoff_ms() -> u64 {
+    500
+}
+
+/// Credentials and endpoint overrides for a single provider, stored as `[providers.<name>]`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    /// Plain-text key or `${ENV_VAR}` reference; prefer the secret store instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Key read from the `${ENV_VAR}` reference or the secret store, never written
+    /// back to `config.toml`.
+    #[serde(skip)]
+    pub resolved_api_key: Option<String>,
+}
+
+impl ProviderConfig {
+    pub fn api_key(&self) -> Option<&str> {
+        match self.api_key.as_deref() {
+            Some(api_key) if env_reference(api_key).is_none() => Some(api_key),
+            _ => self.resolved_api_key.as_deref(),
+        }
+    }
+}
+
+/// Variable name of a `${ENV_VAR}` reference.
+pub fn env_reference(value: &str) -> Option<&str> {
+    value.strip_prefix("${")?.strip_suffix('}')
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GitConfig {
-    pub auto_stage: bool,
+    #[serde(default, deserialize_with = "AutoStage::deserialize_setting")]
+    pub auto_stage: AutoStage,
+    /// Used to pick between `conventional` and `plain` when `style` is unset.
     pub conventional_commits: bool,
     pub diff_context: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<MessageStyle>,
+    /// Prompt fragment for the `custom` style.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_style_prompt: Option<String>,
+    /// Regex the subject must match in the `custom` style.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_style_pattern: Option<String>,
+    /// Conventional Commits types the model may use.
+    #[serde(default = "default_commit_types")]
+    pub commit_types: Vec<String>,
+    /// Allowed scopes; empty allows any scope.
+    #[serde(default)]
+    pub commit_scopes: Vec<String>,
+    #[serde(default = "default_max_subject_length")]
+    pub max_subject_length: usize,
+    #[serde(default)]
+    pub on_invalid: InvalidMessagePolicy,
+    #[serde(default)]
+    pub body: BodyMode,
+    /// Changed diff lines at which `body = "auto"` starts adding a body.
+    #[serde(default = "default_body_threshold")]
+    pub body_threshold: usize,
+    /// Add a trailer for issue keys found in the branch name.
+    #[serde(default = "default_true")]
+    pub issue_from_branch: bool,
+    /// Regex for issue keys; defaults to Jira-style `PROJ-123`, then `#123` numbers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issue_pattern: Option<String>,
+    /// Trailer token used for issue references.
+    #[serde(default = "default_issue_trailer")]
+    pub issue_trailer: String,
+    /// Mark commits that remove public items as breaking instead of only telling
+    /// the model about them.
+    #[serde(default)]
+    pub detect_breaking: bool,
+    /// Always add a `Signed-off-by` trailer.
+    #[serde(default)]
+    pub signoff: bool,
+    /// Suggest scopes derived from the changed paths.
+    #[serde(default = "default_true")]
+    pub infer_scopes: bool,
+    /// Glob-to-scope rules checked before workspace and directory scopes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scope_rules: Vec<ScopeRule>,
+    /// Recent commits sampled to learn the repository's conventions; 0 disables.
+    #[serde(default = "default_history_sample")]
+    pub history_sample: usize,
+    /// Branch `ai-commit pr` compares against; defaults to the remote's default branch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pr_base: Option<String>,
+}
+
+fn default_commit_types() -> Vec<String> {
+    CONVENTIONAL_TYPES.iter().map(ToString::to_string).collect()
+}
+
+const fn default_max_subject_length() -> usize {
+    72
+}
+
+const fn default_body_threshold() -> usize {
+    40
+}
+
+const fn default_history_sample() -> usize {
+    50
+}
+
+const fn default_true() -> bool {
+    true
+}
+
+fn default_issue_trailer() -> String {
+    "Refs".to_string()
+}
+
+impl GitConfig {
+    pub fn message_style(&self) -> MessageStyle {
+        self.style.unwrap_or(if self.conventional_commits {
+            MessageStyle::Conventional
+        } else {
+            MessageStyle::Plain
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +197,42 @@ pub struct UiConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PromptsConfig {
     pub system_prompt: String,
+    /// Overrides the built-in user prompt template.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_prompt: Option<String>,
+    /// Language the commit message is written in.
+    #[serde(default = "default_language")]
+    pub language: String,
+}
+
+/// Whether a model of adapter `model` is served by `provider`; OpenAI's Responses
+/// API models use the same key and endpoint.
+pub fn same_provider(model: AdapterKind, provider: AdapterKind) -> bool {
+    let normalize = |kind| match kind {
+        AdapterKind::OpenAIResp => AdapterKind::OpenAI,
+        kind => kind,
+    };
+    normalize(model) == normalize(provider)
+}
+
+/// Model used for a provider without a configured one.
+pub fn default_model(provider: &str) -> Option<&'static str> {
+    match provider {
+        "openai" => Some("gpt-4o-mini"),
+        "anthropic" => Some("claude-3-5-haiku-20241022"),
+        "gemini" => Some("gemini-2.0-flash"),
+        "groq" => Some("llama-3.1-8b-instant"),
+        "deepseek" => Some("deepseek-chat"),
+        "xai" => Some("grok-beta"),
+        "cohere" => Some("command-r"),
+        "ollama" => Some("gpt-oss:20b"),
+        "github" => Some("gpt-4.1-mini"),
+        _ => None,
+    }
+}
+
+fn default_language() -> String {
+    "English".to_string()
 }
 
 impl Default for AppConfig {
@@ -49,13 +242,36 @@ impl Default for AppConfig {
                 provider: "openai".to_string(),
                 model: "gpt-4o-mini".to_string(),
                 api_key: None,
+                secret_backend: SecretBackend::default(),
                 temperature: 0.1,
                 max_tokens: 150,
+                timeout_secs: default_timeout_secs(),
+                connect_timeout_secs: default_connect_timeout_secs(),
+                max_retries: default_max_retries(),
+                retry_backoff_ms: default_retry_backoff_ms(),
             },
             git: GitConfig {
-                auto_stage: false,
+                auto_stage: AutoStage::default(),
                 conventional_commits: true,
                 diff_context: 3,
+                style: None,
+                custom_style_prompt: None,
+                custom_style_pattern: None,
+                commit_types: default_commit_types(),
+                commit_scopes: Vec::new(),
+                max_subject_length: default_max_subject_length(),
+                on_invalid: InvalidMessagePolicy::default(),
+                body: BodyMode::default(),
+                body_threshold: default_body_threshold(),
+                issue_from_branch: true,
+                issue_pattern: None,
+                issue_trailer: default_issue_trailer(),
+                detect_breaking: false,
+                signoff: false,
+                infer_scopes: true,
+                scope_rules: Vec::new(),
+                history_sample: default_history_sample(),
+                pr_base: None,
             },
             ui: UiConfig {
                 interactive: true,
@@ -64,7 +280,10 @@ impl Default for AppConfig {
             },
             prompts: PromptsConfig {
                 system_prompt: crate::prompts::get_system_prompt(),
+                user_prompt: None,
+                language: default_language(),
             },
+            providers: BTreeMap::new(),
         }
     }
 }
@@ -97,6 +316,8 @@ impl AppConfig {
         let content = std::fs::read_to_string(&config_path)?;
         let mut config: Self = toml::from_str(&content)?;
 
+        config.migrate_legacy_api_key()?;
+
         // Expand environment variables
         config.expand_env_vars();
 
@@ -114,14 +335,81 @@ impl AppConfig {
         Ok(())
     }
 
+    /// Settings for the active provider, if it has a `[providers.<name>]` entry.
+    pub fn provider(&self) -> Option<&ProviderConfig> {
+        self.providers.get(&self.ai.provider)
+    }
+
+    /// Entry for `provider`, created on first use.
+    pub fn provider_mut(&mut self, provider: &str) -> &mut ProviderConfig {
+        self.providers.entry(provider.to_string()).or_default()
+    }
+
+    /// Default model for the active provider.
+    ///
+    /// The global `ai.model` is only used when it belongs to the active provider,
+    /// so switching providers never sends another vendor's model name.
+    pub fn model(&self) -> &str {
+        if let Some(model) = self.provider().and_then(|p| p.model.as_deref()) {
+            return model;
+        }
+        let fits = match AdapterKind::from_lower_str(&self.ai.provider) {
+            Some(provider) => AdapterKind::from_model(&self.ai.model)
+                .is_ok_and(|kind| same_provider(kind, provider)),
+            None => true,
+        };
+        if fits {
+            &self.ai.model
+        } else {
+            default_model(&self.ai.provider).unwrap_or(&self.ai.model)
+        }
+    }
+
+    /// Look up the active provider's key in the secret store if none is set in the file.
+    ///
+    /// Keys are resolved on demand rather than in `load` so that commands which never
+    /// talk to a provider do not touch the keyring or prompt for a passphrase.
+    pub fn resolve_api_key(&mut self) -> Result<()> {
+        let provider = self.ai.provider.clone();
+        if self.provider().and_then(ProviderConfig::api_key).is_none() {
+            let api_key = SecretStore::new(self.ai.secret_backend).get_api_key(&provider)?;
+            self.provider_mut(&provider).resolved_api_key = api_key;
+        }
+        Ok(())
+    }
+
+    /// Older configs kept one `ai.api_key` for every provider; attach it to the active
+    /// one. A plain-text key moves into the secret store, so the file no longer holds it.
+    fn migrate_legacy_api_key(&mut self) -> Result<()> {
+        let Some(api_key) = self.ai.api_key.take() else {
+            return Ok(());
+        };
+        let provider = self.ai.provider.clone();
+
+        if self.provider().is_none_or(|entry| entry.api_key.is_none()) {
+            if env_reference(&api_key).is_some() {
+                self.provider_mut(&provider).api_key = Some(api_key);
+            } else {
+                if let Err(e) =
+                    SecretStore::new(self.ai.secret_backend).set_api_key(&provider, &api_key)
+                {
+                    warn!("Cannot move ai.api_key into the secret store, leaving it in place: {e}");
+                    self.ai.api_key = Some(api_key);
+                    return Ok(());
+                }
+                self.provider_mut(&provider).resolved_api_key = Some(api_key);
+            }
+        }
+
+        self.save()
+    }
+
+    /// Expand `${ENV_VAR}` references. Keys keep the reference in `api_key` so that
+    /// `save` never writes the secret into the file.
     fn expand_env_vars(&mut self) {
-        if let Some(ref api_key) = self.ai.api_key
-            && api_key.starts_with("${")
-            && api_key.ends_with('}')
-        {
-            let env_var = &api_key[2..api_key.len() - 1];
-            if let Ok(value) = std::env::var(env_var) {
-                self.ai.api_key = Some(value);
+        for provider in self.providers.values_mut() {
+            if let Some(env_var) = provider.api_key.as_deref().and_then(env_reference) {
+                provider.resolved_api_key = std::env::var(env_var).ok();
             }
         }
 
@@ -136,3 +424,27 @@ impl AppConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_api_keys_are_resolved_without_touching_the_reference() {
+        let mut config = AppConfig::default();
+        config.provider_mut("openai").api_key = Some("${PATH}".to_string());
+        config.provider_mut("anthropic").api_key = Some("${AI_COMMIT_UNSET_VAR}".to_string());
+        config.provider_mut("groq").api_key = Some("gsk-plain".to_string());
+        config.expand_env_vars();
+
+        let path = std::env::var("PATH").ok();
+        assert_eq!(config.providers["openai"].api_key(), path.as_deref());
+        assert_eq!(config.providers["anthropic"].api_key(), None);
+        assert_eq!(config.providers["groq"].api_key(), Some("gsk-plain"));
+
+        let saved = toml::to_string_pretty(&config).expect("config serializes");
+        assert!(saved.contains(r#"api_key = "${PATH}""#));
+        assert!(saved.contains(r#"api_key = "${AI_COMMIT_UNSET_VAR}""#));
+        assert!(!path.is_some_and(|path| saved.contains(&path)));
+    }
+}
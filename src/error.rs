@@ -2,9 +2,12 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum AppError {
-    #[error("Not in a Git repository")]
+    #[error("Not in a Git repository with a working tree")]
     NotInGitRepo,
 
+    #[error("Cannot change to directory {0}: {1}")]
+    InvalidDirectory(String, String),
+
     #[error("Configuration directory not found")]
     ConfigDirNotFound,
 
@@ -14,9 +17,78 @@ pub enum AppError {
     #[error("Invalid temperature value. Must be between 0.0 and 2.0")]
     InvalidTemperature,
 
+    #[error("Invalid message style: {0}")]
+    InvalidStyle(String),
+
+    #[error("Invalid issue key pattern: {0}")]
+    InvalidIssuePattern(String),
+
+    #[error("Invalid co-author `{0}`, expected `Name <email>`")]
+    InvalidCoAuthor(String),
+
+    #[error("Invalid scope rule {0}")]
+    InvalidScopeRule(String),
+
+    #[error("Invalid path filter {0}")]
+    InvalidPathFilter(String),
+
+    #[error("No changed files match --include/--exclude")]
+    NoMatchingPaths,
+
+    #[error("Invalid commit range: {0}")]
+    InvalidRange(String),
+
+    #[error("Invalid base branch {0}")]
+    InvalidBase(String),
+
+    #[error("No base branch found; pass --base or set git.pr_base")]
+    NoBaseBranch,
+
+    #[error("No commit to amend")]
+    NothingToAmend,
+
+    #[error("{0} has already been pushed to {1}; use --force to rewrite it anyway")]
+    AlreadyPushed(String, String),
+
+    #[error("Invalid split plan: {0}")]
+    InvalidSplitPlan(String),
+
+    #[error("Invalid release notes: {0}")]
+    InvalidReleaseNotes(String),
+
+    #[error("{0} already has a section for {1}")]
+    ReleaseExists(String, String),
+
+    #[error("Resolve merge conflicts before committing")]
+    UnresolvedConflicts,
+
     #[error("No response received from AI")]
     NoResponseFromAi,
 
+    #[error("Generated message is invalid: {}", .0.join("; "))]
+    InvalidCommitMessage(Vec<String>),
+
+    #[error("AI request timed out after {0} seconds")]
+    Timeout(u64),
+
+    #[error("AI provider rate limit exceeded: {0}")]
+    RateLimited(String),
+
+    #[error("AI provider unavailable: {0}")]
+    ServiceUnavailable(String),
+
+    #[error("AI provider error: {0}")]
+    ProviderError(String),
+
+    #[error("AI provider rejected the request: {0}")]
+    RequestRejected(String),
+
+    #[error("Network error: {0}")]
+    Network(String),
+
+    #[error("Operation cancelled")]
+    Cancelled,
+
     #[error("GitHub CLI (gh) not found. Please install GitHub CLI first")]
     GitHubCliNotFound,
 
@@ -26,6 +98,9 @@ pub enum AppError {
     #[error("Authentication error: {0}")]
     AuthenticationError(String),
 
+    #[error("Secret store error: {0}")]
+    SecretStore(String),
+
     #[error("Git error: {0}")]
     Git(#[from] git2::Error),
 
@@ -41,3 +116,24 @@ pub enum AppError {
     #[error("GenAI error: {0}")]
     GenAi(#[from] genai::Error),
 }
+
+impl AppError {
+    /// Whether the failed AI call is worth retrying.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout(_)
+                | Self::RateLimited(_)
+                | Self::ServiceUnavailable(_)
+                | Self::Network(_)
+        )
+    }
+
+    /// Process exit code used when the error terminates the program.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Cancelled => 130,
+            _ => 1,
+        }
+    }
+}
@@ -1,7 +1,20 @@
-use std::{fmt::Write, path::Path};
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    path::Path,
+};
 
 use anyhow::Result;
-use git2::{DiffOptions, ErrorCode, Repository, Status, StatusOptions};
+use clap::ValueEnum;
+use git2::{DiffOptions, ErrorCode, Repository, RepositoryState, Status, StatusOptions};
+use serde::{Deserialize, Deserializer, Serialize};
+use tracing::warn;
+
+use crate::{error::AppError, message::ConventionalSubject, sparse::SparseCheckout};
+
+/// Example subjects shown to the model from the sampled history.
+const HISTORY_EXAMPLES: usize = 8;
 
 pub struct GitRepo {
     repo: Repository,
@@ -14,14 +27,479 @@ pub struct GitStatus {
     pub untracked: Vec<String>,
 }
 
+/// Which changes the commit command stages by itself when nothing is staged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoStage {
+    /// Ask which files to stage
+    #[default]
+    None,
+    /// Stage changes to tracked files, like `git add -u`
+    Modified,
+    /// Stage all changes including new files, like `git add -A`
+    All,
+}
+
+impl AutoStage {
+    /// Accept the policy names as well as the `true`/`false` of older configs.
+    pub fn deserialize_setting<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Setting {
+            Enabled(bool),
+            Policy(AutoStage),
+        }
+
+        Ok(match Setting::deserialize(deserializer)? {
+            Setting::Enabled(true) => Self::All,
+            Setting::Enabled(false) => Self::None,
+            Setting::Policy(policy) => policy,
+        })
+    }
+}
+
+impl std::fmt::Display for AutoStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Modified => write!(f, "modified"),
+            Self::All => write!(f, "all"),
+        }
+    }
+}
+
+/// Where the pre-rewrite HEAD is kept so a reword can be undone.
+pub const BACKUP_REF: &str = "refs/ai-commit/backup";
+
+/// Commits listed per submodule update, to keep large bumps from flooding the prompt.
+const MAX_SUBMODULE_COMMITS: usize = 30;
+
+/// A commit selected for rewording.
+#[derive(Debug, Clone)]
+pub struct CommitInfo {
+    pub id: git2::Oid,
+    pub message: String,
+}
+
+impl CommitInfo {
+    pub fn short_id(&self) -> String {
+        short_id(self.id)
+    }
+
+    pub fn summary(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+}
+
+/// An entry of the stash, `stash@{index}`.
+#[derive(Debug, Clone)]
+pub struct StashEntry {
+    pub index: usize,
+    pub id: git2::Oid,
+    pub message: String,
+}
+
+impl StashEntry {
+    /// Whether the message is git's default `WIP on <branch>: <commit>` rather
+    /// than a description.
+    pub fn is_default_message(&self) -> bool {
+        self.message.starts_with("WIP on ")
+    }
+}
+
+pub fn short_id(id: git2::Oid) -> String {
+    id.to_string()[..7].to_string()
+}
+
+/// A piece of a diff that can be staged or committed on its own.
+#[derive(Debug, Clone, Serialize)]
+pub struct Hunk {
+    /// `<file>.<hunk>`, numbered from 1; `<file>.0` stands for a whole file that
+    /// cannot be split (added, deleted, untracked, binary or renamed).
+    pub id: String,
+    pub path: String,
+    pub header: String,
+    pub patch: String,
+    #[serde(skip)]
+    range: Option<HunkRange>,
+}
+
+impl Hunk {
+    /// The patch under a `diff --git` line naming its file, like a whole-file patch.
+    pub fn file_patch(&self) -> String {
+        if self.range.is_none() {
+            return self.patch.clone();
+        }
+        format!("diff --git a/{0} b/{0}\n{1}", self.path, self.patch)
+    }
+}
+
+/// Line ranges of a hunk in the HEAD and staged versions of a file.
+#[derive(Debug, Clone, Copy)]
+struct HunkRange {
+    old_start: usize,
+    old_lines: usize,
+    new_start: usize,
+    new_lines: usize,
+}
+
+impl HunkRange {
+    /// 0-based start of the affected lines; empty ranges name the line they follow.
+    fn old_begin(self) -> usize {
+        if self.old_lines == 0 {
+            self.old_start
+        } else {
+            self.old_start - 1
+        }
+    }
+
+    fn new_begin(self) -> usize {
+        if self.new_lines == 0 {
+            self.new_start
+        } else {
+            self.new_start - 1
+        }
+    }
+}
+
+/// Commit the index is compared against when building a diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffBase {
+    /// Changes for a new commit
+    Head,
+    /// Changes for amending HEAD: its own changes plus anything newly staged
+    HeadParent,
+}
+
+/// A multi-step git operation the next commit belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RepoOperation {
+    Merge,
+    Rebase,
+    CherryPick,
+    Revert,
+    Bisect,
+    ApplyMailbox,
+}
+
+impl std::fmt::Display for RepoOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Merge => write!(f, "merge"),
+            Self::Rebase => write!(f, "rebase"),
+            Self::CherryPick => write!(f, "cherry-pick"),
+            Self::Revert => write!(f, "revert"),
+            Self::Bisect => write!(f, "bisect"),
+            Self::ApplyMailbox => write!(f, "am"),
+        }
+    }
+}
+
+/// The branch's upstream and how far the two have diverged.
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamStatus {
+    pub name: String,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+/// A submodule whose recorded commit changes, with the commits in between.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubmoduleUpdate {
+    pub path: String,
+    /// Previous commit, absent when the submodule is added.
+    pub from: Option<String>,
+    /// New commit, absent when the submodule is removed.
+    pub to: Option<String>,
+    /// Subjects of the commits the update brings in, newest first.
+    pub commits: Vec<String>,
+    /// Subjects of the commits dropped when the pointer moves backwards.
+    pub dropped: Vec<String>,
+}
+
+/// How often a commit type or scope appears in the sampled history.
+#[derive(Debug, Clone, Serialize)]
+pub struct Frequency {
+    pub name: String,
+    pub count: usize,
+}
+
+/// Conventions observed in the branch's recent non-merge commits.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HistorySample {
+    /// Number of commits sampled.
+    pub commits: usize,
+    /// Commits whose subject follows Conventional Commits.
+    pub conventional: usize,
+    pub types: Vec<Frequency>,
+    pub scopes: Vec<Frequency>,
+    /// Distinct recent subjects, covering as many types as possible.
+    pub examples: Vec<String>,
+}
+
 impl GitRepo {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let repo = Repository::discover(path)?;
+    /// Open the repository containing the current directory, honouring `GIT_DIR`,
+    /// `GIT_WORK_TREE` and the other variables git reads. Linked worktrees open as
+    /// themselves; bare repositories have nothing to commit from and are refused.
+    pub fn open() -> Result<Self> {
+        let repo = Repository::open_from_env().map_err(|e| match e.code() {
+            ErrorCode::NotFound => AppError::NotInGitRepo.into(),
+            _ => anyhow::Error::from(e),
+        })?;
+        if repo.is_bare() || repo.workdir().is_none() {
+            return Err(AppError::NotInGitRepo.into());
+        }
         Ok(Self { repo })
     }
 
-    pub fn is_git_repo(&self) -> bool {
-        !self.repo.is_bare()
+    pub fn workdir(&self) -> Option<&Path> {
+        self.repo.workdir()
+    }
+
+    /// Name of the checked-out branch, or `None` when HEAD is detached.
+    pub fn current_branch(&self) -> Result<Option<String>> {
+        let head = self.repo.find_reference("HEAD")?;
+        Ok(head
+            .symbolic_target()
+            .and_then(|target| target.strip_prefix("refs/heads/"))
+            .map(ToString::to_string))
+    }
+
+    /// Submodule pointer changes between `base` and the index.
+    pub fn submodule_updates(&self, base: DiffBase) -> Result<Vec<SubmoduleUpdate>> {
+        self.submodule_updates_in(&self.index_diff(base)?)
+    }
+
+    /// Submodule pointer changes made by commit `id`.
+    pub fn commit_submodule_updates(&self, id: git2::Oid) -> Result<Vec<SubmoduleUpdate>> {
+        self.submodule_updates_in(&self.commit_diff(id)?)
+    }
+
+    /// Submodule pointer changes between `base` (or the empty tree) and `tip`.
+    pub fn range_submodule_updates(
+        &self,
+        base: Option<git2::Oid>,
+        tip: git2::Oid,
+    ) -> Result<Vec<SubmoduleUpdate>> {
+        self.submodule_updates_in(&self.range_diff(base, tip)?)
+    }
+
+    fn submodule_updates_in(&self, diff: &git2::Diff<'_>) -> Result<Vec<SubmoduleUpdate>> {
+        let mut updates = Vec::new();
+        for delta in diff.deltas() {
+            let (old, new) = (delta.old_file(), delta.new_file());
+            if old.mode() != git2::FileMode::Commit && new.mode() != git2::FileMode::Commit {
+                continue;
+            }
+            let Some(path) = new.path().or_else(|| old.path()) else {
+                continue;
+            };
+            let from = (old.mode() == git2::FileMode::Commit).then(|| old.id());
+            let to = (new.mode() == git2::FileMode::Commit).then(|| new.id());
+
+            // The subjects need the submodule checked out with both commits fetched
+            let (commits, dropped) = match (self.open_submodule(path), from, to) {
+                (Some(submodule), Some(from), Some(to)) => (
+                    subjects_between(&submodule, from, to).unwrap_or_default(),
+                    subjects_between(&submodule, to, from).unwrap_or_default(),
+                ),
+                _ => (Vec::new(), Vec::new()),
+            };
+
+            updates.push(SubmoduleUpdate {
+                path: path.to_string_lossy().into_owned(),
+                from: from.map(short_id),
+                to: to.map(short_id),
+                commits,
+                dropped,
+            });
+        }
+        Ok(updates)
+    }
+
+    fn open_submodule(&self, path: &Path) -> Option<Repository> {
+        let workdir = self.repo.workdir()?;
+        Repository::open(workdir.join(path)).ok()
+    }
+
+    /// Upstream of the checked-out branch with ahead/behind counts, if it has one.
+    pub fn upstream_status(&self) -> Result<Option<UpstreamStatus>> {
+        let head = match self.repo.head() {
+            Ok(head) if head.is_branch() => head,
+            _ => return Ok(None),
+        };
+        let branch = git2::Branch::wrap(head);
+        let Ok(upstream) = branch.upstream() else {
+            return Ok(None);
+        };
+
+        let (Some(local), Some(remote)) = (branch.get().target(), upstream.get().target()) else {
+            return Ok(None);
+        };
+        let (ahead, behind) = self.repo.graph_ahead_behind(local, remote)?;
+
+        Ok(Some(UpstreamStatus {
+            name: upstream.name()?.unwrap_or_default().to_string(),
+            ahead,
+            behind,
+        }))
+    }
+
+    /// The merge, rebase, cherry-pick or similar operation in progress, if any.
+    pub fn operation(&self) -> Option<RepoOperation> {
+        match self.repo.state() {
+            RepositoryState::Clean => None,
+            RepositoryState::Merge => Some(RepoOperation::Merge),
+            RepositoryState::Revert | RepositoryState::RevertSequence => {
+                Some(RepoOperation::Revert)
+            }
+            RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+                Some(RepoOperation::CherryPick)
+            }
+            RepositoryState::Bisect => Some(RepoOperation::Bisect),
+            RepositoryState::Rebase
+            | RepositoryState::RebaseInteractive
+            | RepositoryState::RebaseMerge => Some(RepoOperation::Rebase),
+            RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => {
+                Some(RepoOperation::ApplyMailbox)
+            }
+        }
+    }
+
+    pub fn has_conflicts(&self) -> Result<bool> {
+        Ok(self.repo.index()?.has_conflicts())
+    }
+
+    /// Message git prepared for the merge in progress (`MERGE_MSG`), without comments.
+    pub fn merge_message(&self) -> Result<Option<String>> {
+        if let Ok(prepared) = self.repo.message() {
+            let message: Vec<&str> = prepared
+                .lines()
+                .filter(|line| !line.starts_with('#'))
+                .collect();
+            let message = message.join("\n").trim().to_string();
+            if !message.is_empty() {
+                return Ok(Some(message));
+            }
+        }
+
+        let heads = self.merge_heads()?;
+        Ok((!heads.is_empty()).then(|| {
+            let names: Vec<String> = heads
+                .iter()
+                .map(|oid| format!("'{}'", short_id(*oid)))
+                .collect();
+            format!("Merge commit {}", names.join(", "))
+        }))
+    }
+
+    /// Full id and subject of the commit being reverted (`REVERT_HEAD`).
+    pub fn revert_target(&self) -> Result<Option<(String, String)>> {
+        let Ok(object) = self.repo.revparse_single("REVERT_HEAD") else {
+            return Ok(None);
+        };
+        let commit = object.peel_to_commit()?;
+        Ok(Some((
+            commit.id().to_string(),
+            commit.summary().unwrap_or_default().to_string(),
+        )))
+    }
+
+    /// Subjects of the last `limit` commits reachable from HEAD, newest first.
+    pub fn recent_commit_subjects(&self, limit: usize) -> Result<Vec<String>> {
+        let mut revwalk = self.repo.revwalk()?;
+        if revwalk.push_head().is_err() {
+            // Unborn branch: nothing to show yet
+            return Ok(Vec::new());
+        }
+        revwalk.set_sorting(git2::Sort::TIME)?;
+
+        let mut subjects = Vec::new();
+        for oid in revwalk.take(limit) {
+            let commit = self.repo.find_commit(oid?)?;
+            subjects.push(commit.summary().unwrap_or_default().to_string());
+        }
+
+        Ok(subjects)
+    }
+
+    /// The committer identity as `Name <email>`, for `Signed-off-by` trailers.
+    pub fn signature_identity(&self) -> Result<String> {
+        let signature = self.repo.signature()?;
+        Ok(format!(
+            "{} <{}>",
+            signature.name().unwrap_or_default(),
+            signature.email().unwrap_or_default()
+        ))
+    }
+
+    /// Sample the last `limit` non-merge commits on the current branch, following
+    /// first parents so merged-in branches do not skew the result.
+    pub fn sample_history(&self, limit: usize) -> Result<HistorySample> {
+        let mut revwalk = self.repo.revwalk()?;
+        if limit == 0 || revwalk.push_head().is_err() {
+            return Ok(HistorySample::default());
+        }
+        revwalk.simplify_first_parent()?;
+
+        let mut subjects = Vec::new();
+        for oid in revwalk {
+            let commit = self.repo.find_commit(oid?)?;
+            if commit.parent_count() > 1 {
+                continue;
+            }
+            if let Some(subject) = commit.summary().map(str::trim)
+                && !subject.is_empty()
+            {
+                subjects.push(subject.to_string());
+            }
+            if subjects.len() == limit {
+                break;
+            }
+        }
+
+        let mut types: BTreeMap<String, usize> = BTreeMap::new();
+        let mut scopes: BTreeMap<String, usize> = BTreeMap::new();
+        let mut conventional = 0;
+        let mut examples = Vec::new();
+        let mut example_types = Vec::new();
+
+        for subject in &subjects {
+            let parsed = ConventionalSubject::parse(subject);
+            if let Some(parsed) = &parsed {
+                conventional += 1;
+                *types.entry(parsed.commit_type.clone()).or_default() += 1;
+                if let Some(scope) = &parsed.scope {
+                    *scopes.entry(scope.clone()).or_default() += 1;
+                }
+            }
+
+            // First pass: one example per type, newest first
+            let commit_type = parsed.map(|p| p.commit_type);
+            if examples.len() < HISTORY_EXAMPLES && !example_types.contains(&commit_type) {
+                example_types.push(commit_type);
+                examples.push(subject.clone());
+            }
+        }
+        for subject in &subjects {
+            if examples.len() >= HISTORY_EXAMPLES {
+                break;
+            }
+            if !examples.contains(subject) {
+                examples.push(subject.clone());
+            }
+        }
+
+        Ok(HistorySample {
+            commits: subjects.len(),
+            conventional,
+            types: by_frequency(types),
+            scopes: by_frequency(scopes),
+            examples,
+        })
     }
 
     pub fn get_status(&self) -> Result<GitStatus> {
@@ -120,90 +598,483 @@ impl GitRepo {
         Ok(output)
     }
 
+    /// Stage every change in the working tree, like `git add -A`.
     pub fn stage_all(&self) -> Result<()> {
+        self.stage_pathspec(&["*"])
+    }
+
+    /// Stage changes to tracked files only, like `git add -u`.
+    pub fn stage_modified(&self) -> Result<()> {
         let mut index = self.repo.index()?;
-        index.add_all(std::iter::once(&"*"), git2::IndexAddOption::DEFAULT, None)?;
+        let guard = self.staging_guard(&index)?;
+        index.update_all(std::iter::once(&"*"), Some(&mut guard.update_callback()))?;
         index.write()?;
         Ok(())
     }
 
-    pub fn stage_modified(&self) -> Result<()> {
+    /// Stage untracked files only, leaving changes to tracked files unstaged.
+    pub fn stage_untracked(&self) -> Result<()> {
+        let paths = self.untracked_paths()?;
         let mut index = self.repo.index()?;
-        index.update_all(std::iter::once(&"*"), None)?;
+        let guard = self.staging_guard(&index)?;
+        for path in paths.iter().filter(|path| guard.allows_new(path)) {
+            if self.is_embedded_repo(path) {
+                warn_embedded_repo(path);
+            } else {
+                index.add_path(Path::new(path))?;
+            }
+        }
         index.write()?;
         Ok(())
     }
 
-    pub fn stage_untracked(&self) -> Result<()> {
+    /// Stage new, modified and deleted files matching `pathspecs`, like
+    /// `git add -A <pathspec>...`.
+    pub fn stage_pathspec(&self, pathspecs: &[&str]) -> Result<()> {
         let mut index = self.repo.index()?;
-        index.add_all(std::iter::once(&"*"), git2::IndexAddOption::DEFAULT, None)?;
+        let guard = self.staging_guard(&index)?;
+        index.add_all(
+            pathspecs,
+            git2::IndexAddOption::DEFAULT,
+            Some(&mut guard.add_callback()),
+        )?;
+        index.update_all(pathspecs, Some(&mut guard.update_callback()))?;
         index.write()?;
         Ok(())
     }
 
-    pub fn get_staged_diff(&self) -> Result<String> {
-        // Check if we have any commits
-        let has_commits = self.repo.head().is_ok();
+    /// Stage exactly `paths`, relative to the repository root. Directories stage the
+    /// files below them and paths missing from the working tree are staged as deletions.
+    pub fn stage_paths(&self, paths: &[String]) -> Result<()> {
+        let workdir = self.repo.workdir().ok_or(AppError::NotInGitRepo)?;
+        let mut index = self.repo.index()?;
+        let guard = self.staging_guard(&index)?;
+        for path in paths {
+            let full_path = workdir.join(path);
+            let tracked = index.get_path(Path::new(path), 0).is_some();
+            if !tracked && self.repo.is_path_ignored(Path::new(path))? {
+                warn!("Not staging ignored path {}", path);
+            } else if !tracked && self.is_embedded_repo(path) {
+                warn_embedded_repo(path);
+            } else if full_path.is_dir() {
+                index.add_all(
+                    std::iter::once(path),
+                    git2::IndexAddOption::DEFAULT,
+                    Some(&mut guard.add_callback()),
+                )?;
+            } else if full_path.symlink_metadata().is_ok() {
+                if tracked || guard.allows_new(path) {
+                    index.add_path(Path::new(path.trim_end_matches('/')))?;
+                }
+            } else if !guard.skip_worktree.contains(path) {
+                index.remove_path(Path::new(path))?;
+            }
+        }
+        index.write()?;
+        Ok(())
+    }
 
-        if has_commits {
-            let head = self.repo.head()?.peel_to_tree()?;
-            let mut index = self.repo.index()?;
-            let index_tree = self.repo.find_tree(index.write_tree()?)?;
+    /// Record untracked `paths` as intent-to-add, like `git add -N`: they appear in
+    /// the index with no content, so their changes can be staged hunk by hunk.
+    pub fn intent_to_add(&self, paths: &[String]) -> Result<()> {
+        let workdir = self.repo.workdir().ok_or(AppError::NotInGitRepo)?;
+        let empty_blob = self.repo.blob(&[])?;
+        let mut index = self.repo.index()?;
+        for path in paths {
+            let metadata = workdir.join(path).symlink_metadata()?;
+            index.add(&git2::IndexEntry {
+                ctime: git2::IndexTime::new(0, 0),
+                mtime: git2::IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: file_mode(&metadata),
+                uid: 0,
+                gid: 0,
+                file_size: 0,
+                id: empty_blob,
+                flags: git2::IndexEntryFlag::EXTENDED.bits(),
+                flags_extended: git2::IndexEntryExtendedFlag::INTENT_TO_ADD.bits(),
+                path: path.as_bytes().to_vec(),
+            })?;
+        }
+        index.write()?;
+        Ok(())
+    }
 
-            let mut diff_options = DiffOptions::new();
-            diff_options.context_lines(3);
+    /// Drop the index entries of `paths` that are still only intent-to-add,
+    /// making those files untracked again.
+    pub fn clear_intent_to_add(&self, paths: &[String]) -> Result<()> {
+        let mut index = self.repo.index()?;
+        index.read(false)?;
+        for path in paths {
+            let pending = index.get_path(Path::new(path), 0).is_some_and(|entry| {
+                git2::IndexEntryExtendedFlag::from_bits_truncate(entry.flags_extended)
+                    .is_intent_to_add()
+            });
+            if pending {
+                index.remove_path(Path::new(path))?;
+            }
+        }
+        index.write()?;
+        Ok(())
+    }
 
-            let diff = self.repo.diff_tree_to_tree(
-                Some(&head),
-                Some(&index_tree),
-                Some(&mut diff_options),
-            )?;
+    /// Whether `path` is a repository nested in the working tree.
+    fn is_embedded_repo(&self, path: &str) -> bool {
+        self.repo
+            .workdir()
+            .is_some_and(|root| root.join(path).join(".git").exists())
+    }
+
+    /// What staging must leave alone so it stages no more than `git add` would.
+    fn staging_guard(&self, index: &git2::Index) -> Result<StagingGuard> {
+        let skip_worktree: HashSet<String> = index
+            .iter()
+            .filter(|entry| {
+                git2::IndexEntryExtendedFlag::from_bits_truncate(entry.flags_extended)
+                    .is_skip_worktree()
+            })
+            .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+            .collect();
+        let sparse_enabled = !skip_worktree.is_empty()
+            || self
+                .repo
+                .config()?
+                .get_bool("core.sparseCheckout")
+                .unwrap_or(false);
+        let sparse = if sparse_enabled {
+            SparseCheckout::load(self.repo.path())?
+        } else {
+            None
+        };
+
+        Ok(StagingGuard {
+            skip_worktree,
+            sparse,
+        })
+    }
+
+    /// Untracked files, listed individually even inside untracked directories.
+    pub fn untracked_paths(&self) -> Result<Vec<String>> {
+        let mut status_options = StatusOptions::new();
+        status_options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(false);
+
+        let statuses = self.repo.statuses(Some(&mut status_options))?;
+        Ok(statuses
+            .iter()
+            .filter(|entry| entry.status().contains(Status::WT_NEW))
+            .filter_map(|entry| entry.path().map(ToString::to_string))
+            .collect())
+    }
+
+    /// Lines added and removed per path by the unstaged changes, untracked files included.
+    pub fn unstaged_stats(&self) -> Result<BTreeMap<String, (usize, usize)>> {
+        let diff = self.worktree_diff()?;
+        let mut stats = BTreeMap::new();
+        for (file_index, delta) in diff.deltas().enumerate() {
+            let Some(path) = delta.new_file().path().or_else(|| delta.old_file().path()) else {
+                continue;
+            };
+            let (_, additions, deletions) = match git2::Patch::from_diff(&diff, file_index)? {
+                Some(patch) => patch.line_stats()?,
+                None => (0, 0, 0),
+            };
+            stats.insert(path.to_string_lossy().into_owned(), (additions, deletions));
+        }
+        Ok(stats)
+    }
+
+    /// Patch of the index against `base`, as sent to the model.
+    pub fn get_index_diff(&self, base: DiffBase) -> Result<String> {
+        patch_text(&self.index_diff(base)?)
+    }
+
+    /// Paths that differ between `base` and the index.
+    pub fn changed_paths(&self, base: DiffBase) -> Result<Vec<String>> {
+        Ok(diff_paths(&self.index_diff(base)?))
+    }
+
+    /// The staged changes cut into hunks that can be committed separately.
+    pub fn staged_hunks(&self) -> Result<Vec<Hunk>> {
+        let mut diff_options = DiffOptions::new();
+        diff_options.context_lines(0).interhunk_lines(0);
+        let diff = self.index_diff_with(DiffBase::Head, &mut diff_options)?;
+
+        diff_hunks(&diff)
+    }
 
-            let mut diff_output = String::new();
-            diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
-                match line.origin() {
-                    '+' | '-' | ' ' => {
-                        diff_output.push(line.origin());
-                        diff_output.push_str(std::str::from_utf8(line.content()).unwrap_or(""));
+    /// Changes in the working tree that are not staged yet, including untracked
+    /// files, cut into hunks that can be staged separately.
+    pub fn unstaged_hunks(&self) -> Result<Vec<Hunk>> {
+        diff_hunks(&self.worktree_diff()?)
+    }
+
+    /// Stage the unstaged hunks named by `ids`, as listed by [`Self::unstaged_hunks`].
+    pub fn stage_hunks(&self, ids: &[String]) -> Result<()> {
+        let diff = self.worktree_diff()?;
+
+        // libgit2 reports each file before its hunks, so counting both recovers the ids
+        let file = Cell::new(0);
+        let hunk = Cell::new(0);
+        let selected = |id: String| ids.contains(&id);
+        // Applying cannot create files the index does not know, so those are added afterwards
+        let mut untracked = Vec::new();
+
+        let mut apply_options = git2::ApplyOptions::new();
+        apply_options
+            .delta_callback(|delta| {
+                file.set(file.get() + 1);
+                hunk.set(0);
+                let prefix = format!("{}.", file.get());
+                let chosen = ids.iter().any(|id| id.starts_with(&prefix));
+                match delta {
+                    Some(delta) if delta.status() == git2::Delta::Untracked => {
+                        if chosen && let Some(path) = delta.new_file().path() {
+                            untracked.push(path.to_path_buf());
+                        }
+                        false
                     }
-                    _ => {}
+                    _ => chosen,
                 }
-                true
-            })?;
+            })
+            .hunk_callback(|_| {
+                hunk.set(hunk.get() + 1);
+                selected(format!("{}.0", file.get()))
+                    || selected(format!("{}.{}", file.get(), hunk.get()))
+            });
 
-            Ok(diff_output)
-        } else {
-            // For initial commit, show index vs empty tree
+        self.repo
+            .apply(&diff, git2::ApplyLocation::Index, Some(&mut apply_options))?;
+        drop(apply_options);
+
+        if !untracked.is_empty() {
             let mut index = self.repo.index()?;
-            let index_tree = self.repo.find_tree(index.write_tree()?)?;
-            let empty_tree = self.repo.treebuilder(None)?.write()?;
-            let empty_tree_obj = self.repo.find_tree(empty_tree)?;
+            for path in &untracked {
+                index.add_path(path)?;
+            }
+            index.write()?;
+        }
+        Ok(())
+    }
+
+    /// Commit `groups` of staged hunks in order, each on top of the previous one.
+    ///
+    /// Every commit's tree is built in an in-memory index from HEAD plus the hunks of
+    /// its own and all earlier groups, so the real index and working tree are never
+    /// touched. Once every hunk is committed, HEAD matches what was staged.
+    pub fn commit_hunk_groups(
+        &self,
+        hunks: &[Hunk],
+        groups: &[(Vec<String>, String)],
+    ) -> Result<Vec<git2::Oid>> {
+        let head = match self.repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(ref e) if e.code() == ErrorCode::UnbornBranch => None,
+            Err(e) => return Err(e.into()),
+        };
+        let head_tree = head.as_ref().map(git2::Commit::tree).transpose()?;
+        let staged = self.repo.index()?;
+        let signature = self.repo.signature()?;
 
-            let mut diff_options = DiffOptions::new();
-            diff_options.context_lines(3);
+        let mut included: Vec<&str> = Vec::new();
+        let mut parent = head;
+        let mut commits = Vec::new();
 
-            let diff = self.repo.diff_tree_to_tree(
-                Some(&empty_tree_obj),
-                Some(&index_tree),
-                Some(&mut diff_options),
-            )?;
+        for (ids, message) in groups {
+            included.extend(ids.iter().map(String::as_str));
+
+            let mut index = git2::Index::new()?;
+            if let Some(tree) = &head_tree {
+                index.read_tree(tree)?;
+            }
 
-            let mut diff_output = String::new();
-            diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
-                match line.origin() {
-                    '+' | '-' | ' ' => {
-                        diff_output.push(line.origin());
-                        diff_output.push_str(std::str::from_utf8(line.content()).unwrap_or(""));
+            let mut paths: Vec<&str> = hunks.iter().map(|hunk| hunk.path.as_str()).collect();
+            paths.dedup();
+            for path in paths {
+                let chosen: Vec<&Hunk> = hunks
+                    .iter()
+                    .filter(|hunk| hunk.path == path && included.contains(&hunk.id.as_str()))
+                    .collect();
+                if chosen.is_empty() {
+                    continue;
+                }
+
+                let entry = staged.get_path(Path::new(path), 0);
+                let ranges: Vec<HunkRange> = chosen.iter().filter_map(|hunk| hunk.range).collect();
+                match entry {
+                    Some(mut entry) if !ranges.is_empty() => {
+                        let old = match &head_tree {
+                            Some(tree) => self.blob_at(tree, path)?,
+                            None => Vec::new(),
+                        };
+                        let new = self.repo.find_blob(entry.id)?.content().to_vec();
+                        let content = splice_hunks(&old, &new, &ranges);
+                        entry.id = self.repo.blob(&content)?;
+                        entry.file_size = u32::try_from(content.len()).unwrap_or(u32::MAX);
+                        index.add(&entry)?;
                     }
-                    _ => {}
+                    Some(entry) => index.add(&entry)?,
+                    None => index.remove_path(Path::new(path))?,
                 }
-                true
-            })?;
+            }
 
-            Ok(diff_output)
+            let tree = self.repo.find_tree(index.write_tree_to(&self.repo)?)?;
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+            let commit_id = self.repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parents,
+            )?;
+            commits.push(commit_id);
+            parent = Some(self.repo.find_commit(commit_id)?);
+        }
+
+        Ok(commits)
+    }
+
+    fn blob_at(&self, tree: &git2::Tree<'_>, path: &str) -> Result<Vec<u8>> {
+        match tree.get_path(Path::new(path)) {
+            Ok(entry) => Ok(self.repo.find_blob(entry.id())?.content().to_vec()),
+            Err(ref e) if e.code() == ErrorCode::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
         }
     }
 
+    /// Stash the uncommitted changes under `message`, like `git stash push -m`.
+    pub fn stash_save(&mut self, message: &str, untracked: bool) -> Result<git2::Oid> {
+        let signature = self.repo.signature()?;
+        let flags = if untracked {
+            git2::StashFlags::INCLUDE_UNTRACKED
+        } else {
+            git2::StashFlags::DEFAULT
+        };
+        Ok(self.repo.stash_save(&signature, message, Some(flags))?)
+    }
+
+    /// Stash entries, most recent first.
+    pub fn stashes(&self) -> Result<Vec<StashEntry>> {
+        let reflog = self.repo.reflog("refs/stash")?;
+        Ok(reflog
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| StashEntry {
+                index,
+                id: entry.id_new(),
+                message: entry.message().unwrap_or_default().to_string(),
+            })
+            .collect())
+    }
+
+    /// Every uncommitted change against HEAD, staged or not, optionally with the
+    /// contents of untracked files. Only reads the working tree; the index is left as it is.
+    pub fn get_worktree_diff(&self, untracked: bool) -> Result<String> {
+        patch_text(&self.head_to_workdir_diff(untracked)?)
+    }
+
+    /// Paths with uncommitted changes, optionally including untracked files.
+    pub fn worktree_changed_paths(&self, untracked: bool) -> Result<Vec<String>> {
+        Ok(diff_paths(&self.head_to_workdir_diff(untracked)?))
+    }
+
+    fn head_to_workdir_diff(&self, untracked: bool) -> Result<git2::Diff<'_>> {
+        let head_tree = match self.repo.head() {
+            Ok(head) => Some(head.peel_to_tree()?),
+            Err(ref e) if e.code() == ErrorCode::UnbornBranch => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut diff_options = DiffOptions::new();
+        diff_options
+            .context_lines(3)
+            .include_untracked(untracked)
+            .recurse_untracked_dirs(untracked)
+            .show_untracked_content(untracked);
+        Ok(self
+            .repo
+            .diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut diff_options))?)
+    }
+
+    /// Patch introduced by commit `id` relative to its first parent.
+    pub fn get_commit_diff(&self, id: git2::Oid) -> Result<String> {
+        patch_text(&self.commit_diff(id)?)
+    }
+
+    /// Paths touched by commit `id`.
+    pub fn commit_changed_paths(&self, id: git2::Oid) -> Result<Vec<String>> {
+        Ok(diff_paths(&self.commit_diff(id)?))
+    }
+
+    fn commit_diff(&self, id: git2::Oid) -> Result<git2::Diff<'_>> {
+        let commit = self.repo.find_commit(id)?;
+        let parent_tree = match commit.parent_count() {
+            0 => None,
+            _ => Some(commit.parent(0)?.tree()?),
+        };
+
+        let mut diff_options = DiffOptions::new();
+        diff_options.context_lines(3);
+
+        Ok(self.repo.diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&commit.tree()?),
+            Some(&mut diff_options),
+        )?)
+    }
+
+    fn index_diff(&self, base: DiffBase) -> Result<git2::Diff<'_>> {
+        let mut diff_options = DiffOptions::new();
+        diff_options.context_lines(3);
+        self.index_diff_with(base, &mut diff_options)
+    }
+
+    fn index_diff_with(
+        &self,
+        base: DiffBase,
+        diff_options: &mut DiffOptions,
+    ) -> Result<git2::Diff<'_>> {
+        let head = match self.repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(ref e) if e.code() == ErrorCode::UnbornBranch => None,
+            Err(e) => return Err(e.into()),
+        };
+        let base_commit = match (base, head) {
+            (DiffBase::Head, head) => head,
+            (DiffBase::HeadParent, Some(head)) if head.parent_count() > 0 => Some(head.parent(0)?),
+            (DiffBase::HeadParent, _) => None,
+        };
+        // Without a base commit (initial commit) compare against the empty tree
+        let base_tree = match base_commit {
+            Some(commit) => commit.tree()?,
+            None => self.repo.find_tree(self.repo.treebuilder(None)?.write()?)?,
+        };
+
+        let mut index = self.repo.index()?;
+        let index_tree = self.repo.find_tree(index.write_tree()?)?;
+
+        Ok(self
+            .repo
+            .diff_tree_to_tree(Some(&base_tree), Some(&index_tree), Some(diff_options))?)
+    }
+
+    /// Unstaged changes, with the contents of untracked files, for staging hunks.
+    fn worktree_diff(&self) -> Result<git2::Diff<'_>> {
+        let mut diff_options = DiffOptions::new();
+        diff_options
+            .context_lines(3)
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(true);
+        Ok(self
+            .repo
+            .diff_index_to_workdir(None, Some(&mut diff_options))?)
+    }
+
     pub fn commit(&self, message: &str) -> Result<String> {
         let signature = self.repo.signature()?;
         let mut index = self.repo.index()?;
@@ -216,7 +1087,14 @@ impl GitRepo {
             Err(e) => return Err(e.into()),
         };
 
-        let parents: Vec<&git2::Commit> = parent_commit.iter().collect();
+        // Concluding a merge records every merged head as an extra parent
+        let merge_commits = self
+            .merge_heads()?
+            .into_iter()
+            .map(|oid| self.repo.find_commit(oid))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let parents: Vec<&git2::Commit> = parent_commit.iter().chain(&merge_commits).collect();
 
         let commit_id = self.repo.commit(
             Some("HEAD"),
@@ -226,7 +1104,1181 @@ impl GitRepo {
             &tree,
             &parents,
         )?;
+        self.conclude_operation()?;
+
+        Ok(commit_id.to_string())
+    }
+
+    /// Rewrite HEAD with `message` and the current index, keeping its author.
+    pub fn amend(&self, message: &str) -> Result<String> {
+        let head = self.head_commit()?;
+        let mut index = self.repo.index()?;
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+        let committer = self.repo.signature()?;
+
+        let commit_id = head.amend(
+            Some("HEAD"),
+            None,
+            Some(&committer),
+            None,
+            Some(message),
+            Some(&tree),
+        )?;
 
         Ok(commit_id.to_string())
     }
+
+    /// Full message of the HEAD commit.
+    pub fn head_message(&self) -> Result<String> {
+        Ok(self
+            .head_commit()?
+            .message()
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    /// Name of a remote-tracking branch that already contains HEAD, if any.
+    pub fn head_pushed_to(&self) -> Result<Option<String>> {
+        self.pushed_to(self.head_commit()?.id())
+    }
+
+    /// Name of a remote-tracking branch that already contains commit `id`, if any.
+    pub fn pushed_to(&self, id: git2::Oid) -> Result<Option<String>> {
+        for branch in self.repo.branches(Some(git2::BranchType::Remote))? {
+            let (branch, _) = branch?;
+            let Some(tip) = branch.get().target() else {
+                continue;
+            };
+            if tip == id || self.repo.graph_descendant_of(tip, id)? {
+                return Ok(Some(branch.name()?.unwrap_or_default().to_string()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Non-merge commits in `range` (`base..tip`, or `base` meaning `base..HEAD`),
+    /// oldest first. `tip` must be HEAD or one of its ancestors.
+    pub fn commits_in_range(&self, range: &str) -> Result<Vec<CommitInfo>> {
+        let head = self.head_commit()?.id();
+        let spec = self.repo.revparse(range)?;
+        let (from, to) = if spec.mode().contains(git2::RevparseMode::RANGE) {
+            let to = spec.to().map_or(head, git2::Object::id);
+            (spec.from().map(git2::Object::id), to)
+        } else {
+            (spec.from().map(git2::Object::id), head)
+        };
+
+        if to != head && !self.repo.graph_descendant_of(head, to)? {
+            return Err(
+                AppError::InvalidRange(format!("{range} does not end on HEAD's history")).into(),
+            );
+        }
+
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.push(to)?;
+        if let Some(from) = from {
+            revwalk.hide(from)?;
+        }
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+
+        let mut commits = Vec::new();
+        for oid in revwalk {
+            let commit = self.repo.find_commit(oid?)?;
+            if commit.parent_count() > 1 {
+                return Err(AppError::InvalidRange(format!(
+                    "{range} contains merge commit {}",
+                    short_id(commit.id())
+                ))
+                .into());
+            }
+            commits.push(CommitInfo {
+                id: commit.id(),
+                message: commit.message().unwrap_or_default().to_string(),
+            });
+        }
+
+        Ok(commits)
+    }
+
+    /// Non-merge commits in `from..to` (or `from..HEAD` for a single revision),
+    /// newest first, and the commit the range ends on.
+    ///
+    /// Unlike [`Self::commits_in_range`] the range may end anywhere, such as on a tag.
+    pub fn log_range(&self, range: &str) -> Result<(git2::Oid, Vec<CommitInfo>)> {
+        let spec = self.repo.revparse(range)?;
+        let peel = |object: &git2::Object<'_>| object.peel_to_commit().map(|commit| commit.id());
+        let from = spec.from().map(peel).transpose()?;
+        let to = match spec.to() {
+            Some(to) if spec.mode().contains(git2::RevparseMode::RANGE) => peel(to)?,
+            _ => self.head_id()?,
+        };
+
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.push(to)?;
+        if let Some(from) = from {
+            revwalk.hide(from)?;
+        }
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+
+        let mut commits = Vec::new();
+        for oid in revwalk {
+            let commit = self.repo.find_commit(oid?)?;
+            if commit.parent_count() > 1 {
+                continue;
+            }
+            commits.push(CommitInfo {
+                id: commit.id(),
+                message: commit.message().unwrap_or_default().to_string(),
+            });
+        }
+
+        Ok((to, commits))
+    }
+
+    /// Name of a tag pointing at commit `id`, if any.
+    pub fn tag_at(&self, id: git2::Oid) -> Result<Option<String>> {
+        for name in self.repo.tag_names(None)?.iter().flatten() {
+            let target = self
+                .repo
+                .find_reference(&format!("refs/tags/{name}"))
+                .and_then(|reference| reference.peel_to_commit());
+            if target.is_ok_and(|commit| commit.id() == id) {
+                return Ok(Some(name.to_string()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Commit date of `id` as `YYYY-MM-DD`, in the committer's time zone.
+    pub fn commit_date(&self, id: git2::Oid) -> Result<String> {
+        let time = self.repo.find_commit(id)?.committer().when();
+        let days = (time.seconds() + i64::from(time.offset_minutes()) * 60).div_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+        Ok(format!("{year:04}-{month:02}-{day:02}"))
+    }
+
+    /// Replace the messages of the given commits and re-create every commit up to HEAD
+    /// on top of the rewritten history. Returns the new HEAD.
+    ///
+    /// Only messages change, so each commit is recreated with its original tree on its
+    /// rewritten parent, which is what cherry-picking it would produce, without touching
+    /// the index or working tree. The old HEAD is saved to [`BACKUP_REF`] first.
+    pub fn reword_commits(&self, messages: &[(git2::Oid, String)]) -> Result<git2::Oid> {
+        let head = self.head_commit()?;
+        let Some((first, _)) = messages.first() else {
+            return Ok(head.id());
+        };
+
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.push(head.id())?;
+        for parent in self.repo.find_commit(*first)?.parent_ids() {
+            revwalk.hide(parent)?;
+        }
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+
+        let committer = self.repo.signature()?;
+        let mut rewritten: std::collections::HashMap<git2::Oid, git2::Oid> =
+            std::collections::HashMap::new();
+        let mut new_head = head.id();
+
+        for oid in revwalk {
+            let commit = self.repo.find_commit(oid?)?;
+            if commit.parent_count() > 1 {
+                return Err(AppError::InvalidRange(format!(
+                    "cannot rewrite merge commit {}",
+                    short_id(commit.id())
+                ))
+                .into());
+            }
+
+            let parents = commit
+                .parent_ids()
+                .map(|id| {
+                    self.repo
+                        .find_commit(rewritten.get(&id).copied().unwrap_or(id))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let parents: Vec<&git2::Commit> = parents.iter().collect();
+            let message = messages
+                .iter()
+                .find(|(id, _)| *id == commit.id())
+                .map_or_else(
+                    || commit.message_raw().unwrap_or_default(),
+                    |(_, m)| m.as_str(),
+                );
+
+            new_head = self.repo.commit(
+                None,
+                &commit.author(),
+                &committer,
+                message,
+                &commit.tree()?,
+                &parents,
+            )?;
+            rewritten.insert(commit.id(), new_head);
+        }
+
+        self.move_head(head.id(), new_head, "reword")?;
+        Ok(new_head)
+    }
+
+    /// Replace the commits after `base` up to HEAD with a single commit carrying
+    /// HEAD's tree, like `git reset --soft <base> && git commit`. The index and
+    /// working tree are left alone and the old HEAD is saved to [`BACKUP_REF`].
+    pub fn squash_commits(&self, base: Option<git2::Oid>, message: &str) -> Result<git2::Oid> {
+        let head = self.head_commit()?;
+        let signature = self.repo.signature()?;
+        let parent = base.map(|id| self.repo.find_commit(id)).transpose()?;
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+
+        let new_head = self.repo.commit(
+            None,
+            &signature,
+            &signature,
+            message,
+            &head.tree()?,
+            &parents,
+        )?;
+
+        self.move_head(head.id(), new_head, "squash")?;
+        Ok(new_head)
+    }
+
+    /// Point the checked-out branch (or a detached HEAD) at `new_head`, keeping
+    /// `old_head` in [`BACKUP_REF`].
+    fn move_head(&self, old_head: git2::Oid, new_head: git2::Oid, action: &str) -> Result<()> {
+        self.repo.reference(
+            BACKUP_REF,
+            old_head,
+            true,
+            &format!("ai-commit: backup before {action}"),
+        )?;
+
+        let head_ref = self.repo.head()?;
+        if head_ref.is_branch()
+            && let Some(name) = head_ref.name()
+        {
+            self.repo
+                .find_reference(name)?
+                .set_target(new_head, &format!("ai-commit: {action}"))?;
+        } else {
+            self.repo.set_head_detached(new_head)?;
+        }
+        Ok(())
+    }
+
+    pub fn head_id(&self) -> Result<git2::Oid> {
+        Ok(self.head_commit()?.id())
+    }
+
+    /// Combined patch from `base` (or the empty tree) to `tip`.
+    pub fn get_range_diff(&self, base: Option<git2::Oid>, tip: git2::Oid) -> Result<String> {
+        patch_text(&self.range_diff(base, tip)?)
+    }
+
+    /// Paths changed between `base` (or the empty tree) and `tip`.
+    pub fn range_changed_paths(
+        &self,
+        base: Option<git2::Oid>,
+        tip: git2::Oid,
+    ) -> Result<Vec<String>> {
+        Ok(diff_paths(&self.range_diff(base, tip)?))
+    }
+
+    fn range_diff(&self, base: Option<git2::Oid>, tip: git2::Oid) -> Result<git2::Diff<'_>> {
+        let base_tree = base
+            .map(|id| self.repo.find_commit(id).and_then(|commit| commit.tree()))
+            .transpose()?;
+        let tip_tree = self.repo.find_commit(tip)?.tree()?;
+
+        let mut diff_options = DiffOptions::new();
+        diff_options.context_lines(3);
+
+        Ok(self.repo.diff_tree_to_tree(
+            base_tree.as_ref(),
+            Some(&tip_tree),
+            Some(&mut diff_options),
+        )?)
+    }
+
+    /// Branch to compare against when none is given: the remote's default branch from
+    /// `origin/HEAD`, else the first of `main` and `master` that exists.
+    pub fn default_base(&self) -> Option<String> {
+        if let Ok(reference) = self.repo.find_reference("refs/remotes/origin/HEAD")
+            && let Some(target) = reference.symbolic_target()
+        {
+            return target
+                .strip_prefix("refs/remotes/")
+                .map(ToString::to_string);
+        }
+
+        ["main", "master", "origin/main", "origin/master"]
+            .into_iter()
+            .find(|name| self.repo.revparse_single(name).is_ok())
+            .map(ToString::to_string)
+    }
+
+    /// The merge-base of HEAD and `base`, and the non-merge commits HEAD has on top
+    /// of it, oldest first.
+    pub fn branch_commits(&self, base: &str) -> Result<(git2::Oid, Vec<CommitInfo>)> {
+        let head = self.head_id()?;
+        let base_id = self
+            .repo
+            .revparse_single(base)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|_| AppError::InvalidBase(format!("`{base}`: not found")))?
+            .id();
+        let merge_base = self
+            .repo
+            .merge_base(head, base_id)
+            .map_err(|_| AppError::InvalidBase(format!("`{base}`: shares no history with HEAD")))?;
+
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.push(head)?;
+        revwalk.hide(merge_base)?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+
+        let mut commits = Vec::new();
+        for oid in revwalk {
+            let commit = self.repo.find_commit(oid?)?;
+            // Merges from the base only repeat changes already described
+            if commit.parent_count() > 1 {
+                continue;
+            }
+            commits.push(CommitInfo {
+                id: commit.id(),
+                message: commit.message().unwrap_or_default().to_string(),
+            });
+        }
+
+        Ok((merge_base, commits))
+    }
+
+    /// First parent of commit `id`, or `None` for a root commit.
+    pub fn parent_id(&self, id: git2::Oid) -> Result<Option<git2::Oid>> {
+        let commit = self.repo.find_commit(id)?;
+        Ok((commit.parent_count() > 0)
+            .then(|| commit.parent_id(0))
+            .transpose()?)
+    }
+
+    fn head_commit(&self) -> Result<git2::Commit<'_>> {
+        match self.repo.head() {
+            Ok(head) => Ok(head.peel_to_commit()?),
+            Err(ref e) if e.code() == ErrorCode::UnbornBranch => {
+                Err(AppError::NothingToAmend.into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Commits listed in `MERGE_HEAD` while a merge is in progress.
+    fn merge_heads(&self) -> Result<Vec<git2::Oid>> {
+        if self.repo.state() != RepositoryState::Merge {
+            return Ok(Vec::new());
+        }
+        let content = std::fs::read_to_string(self.repo.path().join("MERGE_HEAD"))?;
+        Ok(content
+            .lines()
+            .map(git2::Oid::from_str)
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Drop the state files `git commit` removes once a merge, revert or
+    /// cherry-pick is committed; sequencer and rebase state are left alone.
+    fn conclude_operation(&self) -> Result<()> {
+        match self.repo.state() {
+            RepositoryState::Merge | RepositoryState::Revert | RepositoryState::CherryPick => {
+                self.repo.cleanup_state()?;
+            }
+            RepositoryState::RevertSequence => {
+                std::fs::remove_file(self.repo.path().join("REVERT_HEAD"))?;
+            }
+            RepositoryState::CherryPickSequence => {
+                std::fs::remove_file(self.repo.path().join("CHERRY_PICK_HEAD"))?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Convert days since 1970-01-01 to a proleptic Gregorian `(year, month, day)`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Howard Hinnant's algorithm, with eras of 400 years starting on March 1st
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month as u32, day as u32)
+}
+
+fn by_frequency(counts: BTreeMap<String, usize>) -> Vec<Frequency> {
+    let mut frequencies: Vec<Frequency> = counts
+        .into_iter()
+        .map(|(name, count)| Frequency { name, count })
+        .collect();
+    frequencies.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    frequencies
+}
+
+/// Render `diff` as patch lines without file headers, as sent to the model.
+fn patch_text(diff: &git2::Diff<'_>) -> Result<String> {
+    let mut diff_output = String::new();
+    diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
+        match line.origin() {
+            '+' | '-' | ' ' => {
+                diff_output.push(line.origin());
+                diff_output.push_str(std::str::from_utf8(line.content()).unwrap_or(""));
+            }
+            // Keep only the `diff --git a/<path> b/<path>` line of the file header
+            'F' => {
+                let header = std::str::from_utf8(line.content()).unwrap_or("");
+                if let Some(first_line) = header.lines().next() {
+                    diff_output.push_str(first_line);
+                    diff_output.push('\n');
+                }
+            }
+            _ => {}
+        }
+        true
+    })?;
+
+    Ok(diff_output)
+}
+
+/// Subjects of the commits reachable from `to` but not `from`, newest first.
+fn subjects_between(repo: &Repository, from: git2::Oid, to: git2::Oid) -> Result<Vec<String>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(to)?;
+    revwalk.hide(from)?;
+
+    let mut subjects = Vec::new();
+    for id in revwalk.take(MAX_SUBMODULE_COMMITS) {
+        let commit = repo.find_commit(id?)?;
+        subjects.push(commit.summary().unwrap_or_default().to_string());
+    }
+    Ok(subjects)
+}
+
+fn diff_paths(diff: &git2::Diff<'_>) -> Vec<String> {
+    diff.deltas()
+        .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Paths staging skips: entries a sparse checkout left out of the working tree,
+/// new files outside the sparse patterns and repositories nested in the worktree.
+struct StagingGuard {
+    skip_worktree: HashSet<String>,
+    sparse: Option<SparseCheckout>,
+}
+
+impl StagingGuard {
+    fn allows_new(&self, path: &str) -> bool {
+        self.sparse
+            .as_ref()
+            .is_none_or(|sparse| sparse.contains(path))
+    }
+
+    /// Callback for `Index::add_all`, returning 0 to add a path and 1 to skip it.
+    fn add_callback(&self) -> impl FnMut(&Path, &[u8]) -> i32 + '_ {
+        |path, _| {
+            let path = path.to_string_lossy();
+            // libgit2 reports a repository nested in the worktree as a directory
+            if path.ends_with('/') {
+                warn_embedded_repo(&path);
+                return 1;
+            }
+            i32::from(!self.allows_new(&path))
+        }
+    }
+
+    /// Callback for `Index::update_all`, returning 0 to update a path and 1 to skip it.
+    fn update_callback(&self) -> impl FnMut(&Path, &[u8]) -> i32 + '_ {
+        |path, _| i32::from(self.skip_worktree.contains(path.to_string_lossy().as_ref()))
+    }
+}
+
+fn warn_embedded_repo(path: &str) {
+    warn!(
+        "Not staging embedded repository {}; add it with `git submodule add`",
+        path
+    );
+}
+
+/// Index mode for a file in the working tree.
+fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.file_type().is_symlink() {
+            return 0o120_000;
+        }
+        if metadata.permissions().mode() & 0o111 != 0 {
+            return 0o100_755;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    0o100_644
+}
+
+/// Cut `diff` into hunks; files that cannot be split become a single `<file>.0` hunk.
+fn diff_hunks(diff: &git2::Diff<'_>) -> Result<Vec<Hunk>> {
+    let mut hunks = Vec::new();
+    for (file_index, delta) in diff.deltas().enumerate() {
+        let file = file_index + 1;
+        let path = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let Some(mut patch) = git2::Patch::from_diff(diff, file_index)? else {
+            continue;
+        };
+        let splittable = delta.status() == git2::Delta::Modified
+            && !delta.flags().is_binary()
+            && patch.num_hunks() > 0;
+        if !splittable {
+            hunks.push(Hunk {
+                id: format!("{file}.0"),
+                path,
+                header: format!("{:?} file", delta.status()).to_lowercase(),
+                patch: String::from_utf8_lossy(&patch.to_buf()?).into_owned(),
+                range: None,
+            });
+            continue;
+        }
+
+        for hunk_index in 0..patch.num_hunks() {
+            let (hunk, line_count) = patch.hunk(hunk_index)?;
+            let mut text = String::new();
+            for line_index in 0..line_count {
+                let line = patch.line_in_hunk(hunk_index, line_index)?;
+                if matches!(line.origin(), '+' | '-' | ' ') {
+                    text.push(line.origin());
+                    text.push_str(&String::from_utf8_lossy(line.content()));
+                }
+            }
+            hunks.push(Hunk {
+                id: format!("{file}.{}", hunk_index + 1),
+                path: path.clone(),
+                header: String::from_utf8_lossy(hunk.header()).trim().to_string(),
+                patch: text,
+                range: Some(HunkRange {
+                    old_start: hunk.old_start() as usize,
+                    old_lines: hunk.old_lines() as usize,
+                    new_start: hunk.new_start() as usize,
+                    new_lines: hunk.new_lines() as usize,
+                }),
+            });
+        }
+    }
+
+    Ok(hunks)
+}
+
+/// Apply the `ranges` of `new` (hunks from a diff of `old` to `new`) to `old`,
+/// leaving the rest of `old` unchanged.
+fn splice_hunks(old: &[u8], new: &[u8], ranges: &[HunkRange]) -> Vec<u8> {
+    let old_lines: Vec<&[u8]> = old.split_inclusive(|&b| b == b'\n').collect();
+    let new_lines: Vec<&[u8]> = new.split_inclusive(|&b| b == b'\n').collect();
+
+    let mut ranges = ranges.to_vec();
+    ranges.sort_by_key(|range| range.old_start);
+
+    let mut content = Vec::with_capacity(new.len().max(old.len()));
+    let mut position = 0;
+    for range in ranges {
+        let begin = range.old_begin();
+        for line in &old_lines[position.min(begin)..begin] {
+            content.extend_from_slice(line);
+        }
+        let new_begin = range.new_begin();
+        for line in &new_lines[new_begin..new_begin + range.new_lines] {
+            content.extend_from_slice(line);
+        }
+        position = begin + range.old_lines;
+    }
+    for line in &old_lines[position.min(old_lines.len())..] {
+        content.extend_from_slice(line);
+    }
+
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const GITLINK: &str = "<gitlink>";
+    const INTENT_TO_ADD: &str = "<intent-to-add>";
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().expect("file has a parent")).expect("create dirs");
+        fs::write(path, content).expect("write file");
+    }
+
+    /// A repository with every kind of change staging has to handle:
+    /// - `tracked.txt` modified and `removed.txt` deleted
+    /// - `new.txt` untracked, and `newdir/sub/deep.txt` in an untracked directory
+    /// - `debug.log` ignored
+    /// - `outside/kept.txt` skip-worktree and missing, as a sparse checkout leaves it,
+    ///   and `outside/stray.txt` new outside the sparse patterns
+    /// - `nested/` an embedded repository
+    fn fixture() -> (TempDir, GitRepo) {
+        let dir = TempDir::new().expect("temp dir");
+        let root = dir.path();
+        let repo = Repository::init(root).expect("init");
+
+        write(root, ".gitignore", "*.log\n");
+        write(root, "tracked.txt", "old\n");
+        write(root, "removed.txt", "removed\n");
+        write(root, "unchanged.txt", "unchanged\n");
+        write(root, "outside/kept.txt", "kept\n");
+
+        let mut index = repo.index().expect("index");
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .expect("add");
+        {
+            let tree = repo
+                .find_tree(index.write_tree().expect("tree"))
+                .expect("find tree");
+            let signature = git2::Signature::now("Test", "test@example.com").expect("signature");
+            repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+                .expect("commit");
+        }
+
+        // Leave `outside/` out of the checkout like `git sparse-checkout set` would
+        repo.config()
+            .expect("config")
+            .set_bool("core.sparseCheckout", true)
+            .expect("set sparse");
+        write(repo.path(), "info/sparse-checkout", "/*\n!/outside/\n");
+        let mut entry = index
+            .get_path(Path::new("outside/kept.txt"), 0)
+            .expect("kept entry");
+        entry.flags |= git2::IndexEntryFlag::EXTENDED.bits();
+        entry.flags_extended |= git2::IndexEntryExtendedFlag::SKIP_WORKTREE.bits();
+        index.add(&entry).expect("skip worktree");
+        index.write().expect("write index");
+        fs::remove_file(root.join("outside/kept.txt")).expect("remove kept");
+
+        write(root, "tracked.txt", "new\n");
+        fs::remove_file(root.join("removed.txt")).expect("remove");
+        write(root, "new.txt", "new file\n");
+        write(root, "newdir/sub/deep.txt", "deep\n");
+        write(root, "debug.log", "log\n");
+        write(root, "outside/stray.txt", "stray\n");
+        Repository::init(root.join("nested")).expect("init nested");
+        write(root, "nested/inner.txt", "inner\n");
+
+        (dir, GitRepo { repo })
+    }
+
+    /// Every index entry with the content it stages.
+    fn index_entries(repo: &GitRepo) -> Vec<(String, String)> {
+        let mut index = repo.repo.index().expect("index");
+        index.read(true).expect("read index");
+        index
+            .iter()
+            .map(|entry| {
+                let path = String::from_utf8_lossy(&entry.path).into_owned();
+                let flags = git2::IndexEntryExtendedFlag::from_bits_truncate(entry.flags_extended);
+                let content = if entry.mode == 0o160_000 {
+                    GITLINK.to_string()
+                } else if flags.is_intent_to_add() {
+                    INTENT_TO_ADD.to_string()
+                } else {
+                    let blob = repo.repo.find_blob(entry.id).expect("blob");
+                    String::from_utf8_lossy(blob.content()).into_owned()
+                };
+                (path, content)
+            })
+            .collect()
+    }
+
+    fn entries(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected
+            .iter()
+            .map(|(path, content)| ((*path).to_string(), (*content).to_string()))
+            .collect()
+    }
+
+    const HEAD_ENTRIES: &[(&str, &str)] = &[
+        (".gitignore", "*.log\n"),
+        ("outside/kept.txt", "kept\n"),
+        ("removed.txt", "removed\n"),
+        ("tracked.txt", "old\n"),
+        ("unchanged.txt", "unchanged\n"),
+    ];
+
+    #[test]
+    fn stage_all_skips_ignored_sparse_and_embedded_paths() {
+        let (_dir, repo) = fixture();
+        repo.stage_all().expect("stage all");
+        assert_eq!(
+            index_entries(&repo),
+            entries(&[
+                (".gitignore", "*.log\n"),
+                ("new.txt", "new file\n"),
+                ("newdir/sub/deep.txt", "deep\n"),
+                ("outside/kept.txt", "kept\n"),
+                ("tracked.txt", "new\n"),
+                ("unchanged.txt", "unchanged\n"),
+            ])
+        );
+    }
+
+    #[test]
+    fn stage_modified_stages_tracked_changes_only() {
+        let (_dir, repo) = fixture();
+        repo.stage_modified().expect("stage modified");
+        assert_eq!(
+            index_entries(&repo),
+            entries(&[
+                (".gitignore", "*.log\n"),
+                ("outside/kept.txt", "kept\n"),
+                ("tracked.txt", "new\n"),
+                ("unchanged.txt", "unchanged\n"),
+            ])
+        );
+    }
+
+    #[test]
+    fn stage_untracked_adds_new_files_inside_the_checkout() {
+        let (_dir, repo) = fixture();
+        repo.stage_untracked().expect("stage untracked");
+        assert_eq!(
+            index_entries(&repo),
+            entries(&[
+                (".gitignore", "*.log\n"),
+                ("new.txt", "new file\n"),
+                ("newdir/sub/deep.txt", "deep\n"),
+                ("outside/kept.txt", "kept\n"),
+                ("removed.txt", "removed\n"),
+                ("tracked.txt", "old\n"),
+                ("unchanged.txt", "unchanged\n"),
+            ])
+        );
+    }
+
+    #[test]
+    fn stage_pathspec_limits_staging_to_matches() {
+        let (_dir, repo) = fixture();
+        repo.stage_pathspec(&["newdir", "removed.txt"])
+            .expect("stage newdir");
+        assert_eq!(
+            index_entries(&repo),
+            entries(&[
+                (".gitignore", "*.log\n"),
+                ("newdir/sub/deep.txt", "deep\n"),
+                ("outside/kept.txt", "kept\n"),
+                ("tracked.txt", "old\n"),
+                ("unchanged.txt", "unchanged\n"),
+            ])
+        );
+    }
+
+    #[test]
+    fn stage_pathspec_leaves_paths_outside_the_sparse_checkout() {
+        let (_dir, repo) = fixture();
+        repo.stage_pathspec(&["outside"]).expect("stage outside");
+        assert_eq!(index_entries(&repo), entries(HEAD_ENTRIES));
+    }
+
+    #[test]
+    fn stage_paths_stages_exactly_the_allowed_paths() {
+        let (_dir, repo) = fixture();
+        let paths = [
+            "tracked.txt",
+            "removed.txt",
+            "newdir",
+            "debug.log",
+            "outside/kept.txt",
+            "outside/stray.txt",
+            "nested/",
+        ]
+        .map(ToString::to_string);
+        repo.stage_paths(&paths).expect("stage paths");
+        assert_eq!(
+            index_entries(&repo),
+            entries(&[
+                (".gitignore", "*.log\n"),
+                ("newdir/sub/deep.txt", "deep\n"),
+                ("outside/kept.txt", "kept\n"),
+                ("tracked.txt", "new\n"),
+                ("unchanged.txt", "unchanged\n"),
+            ])
+        );
+    }
+
+    #[test]
+    fn stage_paths_stages_untracked_files_listed_one_by_one() {
+        let (_dir, repo) = fixture();
+        let paths = repo.untracked_paths().expect("untracked paths");
+        repo.stage_paths(&paths).expect("stage paths");
+        assert_eq!(
+            index_entries(&repo),
+            entries(&[
+                (".gitignore", "*.log\n"),
+                ("new.txt", "new file\n"),
+                ("newdir/sub/deep.txt", "deep\n"),
+                ("outside/kept.txt", "kept\n"),
+                ("removed.txt", "removed\n"),
+                ("tracked.txt", "old\n"),
+                ("unchanged.txt", "unchanged\n"),
+            ])
+        );
+    }
+
+    #[test]
+    fn intent_to_add_is_cleared_unless_staged() {
+        let (_dir, repo) = fixture();
+        let paths = ["new.txt", "newdir/sub/deep.txt"].map(ToString::to_string);
+        repo.intent_to_add(&paths).expect("intent to add");
+        assert_eq!(
+            index_entries(&repo),
+            entries(&[
+                (".gitignore", "*.log\n"),
+                ("new.txt", INTENT_TO_ADD),
+                ("newdir/sub/deep.txt", INTENT_TO_ADD),
+                ("outside/kept.txt", "kept\n"),
+                ("removed.txt", "removed\n"),
+                ("tracked.txt", "old\n"),
+                ("unchanged.txt", "unchanged\n"),
+            ])
+        );
+
+        repo.stage_paths(&paths[..1]).expect("stage new.txt");
+        repo.clear_intent_to_add(&paths)
+            .expect("clear intent to add");
+        assert_eq!(
+            index_entries(&repo),
+            entries(&[
+                (".gitignore", "*.log\n"),
+                ("new.txt", "new file\n"),
+                ("outside/kept.txt", "kept\n"),
+                ("removed.txt", "removed\n"),
+                ("tracked.txt", "old\n"),
+                ("unchanged.txt", "unchanged\n"),
+            ])
+        );
+    }
+
+    #[test]
+    fn staging_updates_submodule_pointers() {
+        let (dir, repo) = fixture();
+        let signature = git2::Signature::now("Test", "test@example.com").expect("signature");
+        let submodule = Repository::open(dir.path().join("nested")).expect("open nested");
+        let commit = |message: &str, parents: &[&git2::Commit<'_>]| {
+            let mut index = submodule.index().expect("index");
+            index
+                .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+                .expect("add");
+            let tree = submodule
+                .find_tree(index.write_tree().expect("tree"))
+                .expect("find tree");
+            submodule
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    message,
+                    &tree,
+                    parents,
+                )
+                .expect("commit")
+        };
+        let first = commit("first", &[]);
+        let first_commit = submodule.find_commit(first).expect("first commit");
+        write(dir.path(), "nested/inner.txt", "changed\n");
+        let second = commit("second", &[&first_commit]);
+
+        // Record `nested` as a submodule at its first commit
+        let mut index = repo.repo.index().expect("index");
+        index
+            .add(&git2::IndexEntry {
+                ctime: git2::IndexTime::new(0, 0),
+                mtime: git2::IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: 0o160_000,
+                uid: 0,
+                gid: 0,
+                file_size: 0,
+                id: first,
+                flags: 0,
+                flags_extended: 0,
+                path: b"nested".to_vec(),
+            })
+            .expect("add gitlink");
+        index.write().expect("write index");
+
+        repo.stage_modified().expect("stage modified");
+        let mut index = repo.repo.index().expect("index");
+        index.read(true).expect("read index");
+        let entry = index.get_path(Path::new("nested"), 0).expect("gitlink");
+        assert_eq!(entry.mode, 0o160_000);
+        assert_eq!(entry.id, second);
+        assert!(index.get_path(Path::new("nested/inner.txt"), 0).is_none());
+    }
+
+    #[test]
+    fn civil_dates_handle_leap_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        // 2023 and 2100 are not leap years
+        assert_eq!(civil_from_days(19_417), (2023, 3, 1));
+        assert_eq!(civil_from_days(47_541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn commit_dates_use_the_committer_time_zone() {
+        let dir = TempDir::new().expect("temp dir");
+        let repo = Repository::init(dir.path()).expect("init");
+        let id = {
+            let tree = repo
+                .find_tree(repo.index().expect("index").write_tree().expect("tree"))
+                .expect("find tree");
+            // 2024-02-28 23:30 UTC, already the leap day an hour east of UTC
+            let when = git2::Time::new(1_709_163_000, 60);
+            let signature =
+                git2::Signature::new("Test", "test@example.com", &when).expect("signature");
+            repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+                .expect("commit")
+        };
+
+        let repo = GitRepo { repo };
+        assert_eq!(repo.commit_date(id).expect("date"), "2024-02-29");
+    }
+
+    /// A repository whose HEAD holds `files`, with `staged` written and staged on top;
+    /// `None` deletes the file.
+    fn split_fixture(
+        files: &[(&str, &str)],
+        staged: &[(&str, Option<&str>)],
+    ) -> (TempDir, GitRepo) {
+        let dir = TempDir::new().expect("temp dir");
+        let root = dir.path();
+        let repo = Repository::init(root).expect("init");
+        let mut config = repo.config().expect("config");
+        config.set_str("user.name", "Test").expect("set name");
+        config
+            .set_str("user.email", "test@example.com")
+            .expect("set email");
+
+        for (path, content) in files {
+            write(root, path, content);
+        }
+        let mut index = repo.index().expect("index");
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .expect("add");
+        {
+            let tree = repo
+                .find_tree(index.write_tree().expect("tree"))
+                .expect("find tree");
+            let signature = repo.signature().expect("signature");
+            repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+                .expect("commit");
+        }
+
+        for (path, content) in staged {
+            match content {
+                Some(content) => {
+                    write(root, path, content);
+                    index.add_path(Path::new(path)).expect("stage");
+                }
+                None => {
+                    fs::remove_file(root.join(path)).expect("remove");
+                    index.remove_path(Path::new(path)).expect("stage removal");
+                }
+            }
+        }
+        index.write().expect("write index");
+
+        (dir, GitRepo { repo })
+    }
+
+    /// Content of `path` in the tree of `commit`, `None` if it is absent.
+    fn file_at(repo: &GitRepo, commit: git2::Oid, path: &str) -> Option<String> {
+        let tree = repo
+            .repo
+            .find_commit(commit)
+            .expect("commit")
+            .tree()
+            .expect("tree");
+        let entry = tree.get_path(Path::new(path)).ok()?;
+        let blob = repo.repo.find_blob(entry.id()).expect("blob");
+        Some(String::from_utf8(blob.content().to_vec()).expect("utf-8"))
+    }
+
+    /// Commit `groups` of hunks, given by their headers, one group per commit.
+    fn commit_groups(repo: &GitRepo, groups: &[&[&str]]) -> Vec<git2::Oid> {
+        let hunks = repo.staged_hunks().expect("hunks");
+        let groups: Vec<(Vec<String>, String)> = groups
+            .iter()
+            .enumerate()
+            .map(|(n, headers)| {
+                let ids = headers
+                    .iter()
+                    .map(|header| {
+                        hunks
+                            .iter()
+                            .find(|hunk| hunk.header.starts_with(header))
+                            .unwrap_or_else(|| panic!("no hunk {header} in {hunks:?}"))
+                            .id
+                            .clone()
+                    })
+                    .collect();
+                (ids, format!("commit {n}"))
+            })
+            .collect();
+        repo.commit_hunk_groups(&hunks, &groups)
+            .expect("commit groups")
+    }
+
+    #[test]
+    fn split_commits_splice_adjacent_hunks_in_any_order() {
+        let (_dir, repo) = split_fixture(
+            &[("lines.txt", "1\n2\n3\n4\n5\n6\n7\n8\n")],
+            &[("lines.txt", Some("0\n1\n2\nthree\n4\nfive\n5b\n6\n8\n"))],
+        );
+
+        let commits = commit_groups(
+            &repo,
+            &[
+                &["@@ -5 +6,2 @@"],
+                &["@@ -0,0 +1 @@", "@@ -7 +8,0 @@"],
+                &["@@ -3 +4 @@"],
+            ],
+        );
+
+        let contents: Vec<String> = commits
+            .iter()
+            .map(|&commit| file_at(&repo, commit, "lines.txt").expect("file"))
+            .collect();
+        assert_eq!(
+            contents,
+            [
+                "1\n2\n3\n4\nfive\n5b\n6\n7\n8\n",
+                "0\n1\n2\n3\n4\nfive\n5b\n6\n8\n",
+                "0\n1\n2\nthree\n4\nfive\n5b\n6\n8\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn split_commits_delete_and_add_whole_files() {
+        let (_dir, repo) = split_fixture(
+            &[("gone.txt", "gone\n"), ("kept.txt", "old\n")],
+            &[
+                ("gone.txt", None),
+                ("kept.txt", Some("new\n")),
+                ("added.txt", Some("added\n")),
+            ],
+        );
+        let hunks = repo.staged_hunks().expect("hunks");
+        let id = |path: &str| {
+            hunks
+                .iter()
+                .find(|hunk| hunk.path == path)
+                .expect("hunk")
+                .id
+                .clone()
+        };
+
+        let commits = repo
+            .commit_hunk_groups(
+                &hunks,
+                &[
+                    (vec![id("gone.txt")], "remove gone".to_string()),
+                    (vec![id("kept.txt"), id("added.txt")], "update".to_string()),
+                ],
+            )
+            .expect("commit groups");
+
+        assert_eq!(file_at(&repo, commits[0], "gone.txt"), None);
+        assert_eq!(
+            file_at(&repo, commits[0], "kept.txt").as_deref(),
+            Some("old\n")
+        );
+        assert_eq!(file_at(&repo, commits[0], "added.txt"), None);
+        assert_eq!(file_at(&repo, commits[1], "gone.txt"), None);
+        assert_eq!(
+            file_at(&repo, commits[1], "kept.txt").as_deref(),
+            Some("new\n")
+        );
+        assert_eq!(
+            file_at(&repo, commits[1], "added.txt").as_deref(),
+            Some("added\n")
+        );
+    }
+
+    #[test]
+    fn split_commits_keep_missing_trailing_newlines() {
+        let (_dir, repo) = split_fixture(
+            &[("last.txt", "a\nb\nc"), ("tail.txt", "x\nm\ny")],
+            &[
+                ("last.txt", Some("A\nb\nC")),
+                ("tail.txt", Some("X\nm\ny\nz\n")),
+            ],
+        );
+        let hunks = repo.staged_hunks().expect("hunks");
+        let ids = |path: &str| -> Vec<String> {
+            hunks
+                .iter()
+                .filter(|hunk| hunk.path == path)
+                .map(|hunk| hunk.id.clone())
+                .collect()
+        };
+        let (last, tail) = (ids("last.txt"), ids("tail.txt"));
+        assert_eq!((last.len(), tail.len()), (2, 2));
+
+        let commits = repo
+            .commit_hunk_groups(
+                &hunks,
+                &[
+                    (vec![last[1].clone(), tail[1].clone()], "ends".to_string()),
+                    (vec![last[0].clone(), tail[0].clone()], "starts".to_string()),
+                ],
+            )
+            .expect("commit groups");
+
+        assert_eq!(
+            file_at(&repo, commits[0], "last.txt").as_deref(),
+            Some("a\nb\nC")
+        );
+        assert_eq!(
+            file_at(&repo, commits[0], "tail.txt").as_deref(),
+            Some("x\nm\ny\nz\n")
+        );
+        assert_eq!(
+            file_at(&repo, commits[1], "last.txt").as_deref(),
+            Some("A\nb\nC")
+        );
+        assert_eq!(
+            file_at(&repo, commits[1], "tail.txt").as_deref(),
+            Some("X\nm\ny\nz\n")
+        );
+    }
 }
@@ -1,29 +1,56 @@
 mod ai;
+mod changelog;
 mod cli;
 mod config;
 mod error;
 mod git;
+mod message;
+mod pr;
 mod prompts;
+mod scopes;
+mod secrets;
+mod sparse;
+mod split;
+mod style;
+mod trailers;
 
 use anyhow::Result;
 use clap::Parser;
 use cli::{Cli, Commands};
+use error::AppError;
 use tracing::debug;
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("Error: {e:?}");
+        let exit_code = e.downcast_ref::<AppError>().map_or(1, AppError::exit_code);
+        std::process::exit(exit_code);
+    }
+}
+
+async fn run() -> Result<()> {
     // Initialize tracing
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::from_default_env()
                 .add_directive("ai_commit=info".parse()?),
         )
+        // Keep stdout for command output such as `squash-msg`
+        .with_writer(std::io::stderr)
         .init();
 
     let cli = Cli::parse();
 
     debug!("Starting ai-commit with args: {:?}", cli);
 
+    // Like `git -C`, everything below runs as if started in that directory
+    if let Some(directory) = &cli.directory {
+        std::env::set_current_dir(directory).map_err(|e| {
+            AppError::InvalidDirectory(directory.display().to_string(), e.to_string())
+        })?;
+    }
+
     match cli.command {
         Some(Commands::Config { action }) => {
             cli::config::handle_config_command(action)?;
@@ -34,9 +61,30 @@ async fn main() -> Result<()> {
         Some(Commands::Auth) => {
             cli::auth::handle_auth_command()?;
         }
+        Some(Commands::Prompt { action }) => {
+            cli::prompt::handle_prompt_command(action)?;
+        }
+        Some(Commands::Reword(args)) => {
+            cli::reword::handle_reword_command(args).await?;
+        }
+        Some(Commands::SquashMsg(args)) => {
+            cli::squash::handle_squash_command(args).await?;
+        }
+        Some(Commands::Split(args)) => {
+            cli::split::handle_split_command(args).await?;
+        }
+        Some(Commands::Stash(args)) => {
+            cli::stash::handle_stash_command(args).await?;
+        }
+        Some(Commands::Pr(args)) => {
+            cli::pr::handle_pr_command(args).await?;
+        }
+        Some(Commands::Changelog(args)) => {
+            cli::changelog::handle_changelog_command(args).await?;
+        }
         None => {
             // Default: commit command
-            cli::commit::handle_commit_command(cli.all, cli.yes, cli.model, cli.context).await?;
+            cli::commit::handle_commit_command(cli.commit).await?;
         }
     }
 
@@ -0,0 +1,418 @@
+use std::{fmt, sync::LazyLock};
+
+use clap::ValueEnum;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Column at which commit bodies are wrapped.
+pub const BODY_WIDTH: usize = 72;
+
+static CONVENTIONAL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?<type>[A-Za-z]+)(\((?<scope>[^()]*)\))?(?<breaking>!)?:\s*(?<description>.*)$")
+        .expect("valid conventional commit regex")
+});
+static FOOTER_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?<token>BREAKING[ -]CHANGE|[A-Za-z][A-Za-z0-9-]*)(?<sep>: | #)(?<value>.+)$")
+        .expect("valid footer regex")
+});
+static PREAMBLE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)^(sure[,!.]?\s*)?(here('s| is)|below is|this is)?\s*(the|a|an|your)?\s*(suggested|generated|proposed)?\s*(conventional\s+)?commit(\s+message)?[^:\n]{0,40}:\s*$",
+    )
+    .expect("valid preamble regex")
+});
+
+/// When the generated message should include a body below the subject.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyMode {
+    /// Subject line only
+    #[default]
+    Off,
+    /// Add a body when the staged diff is large
+    Auto,
+    /// Always add a body
+    Always,
+}
+
+impl BodyMode {
+    pub fn includes_body(self, changed_lines: usize, auto_threshold: usize) -> bool {
+        match self {
+            Self::Off => false,
+            Self::Auto => changed_lines >= auto_threshold,
+            Self::Always => true,
+        }
+    }
+}
+
+impl fmt::Display for BodyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Auto => write!(f, "auto"),
+            Self::Always => write!(f, "always"),
+        }
+    }
+}
+
+/// A git trailer such as `Refs: #123` or `BREAKING CHANGE: drop v1 API`.
+///
+/// Always rendered as `Token: value`, the only separator `git interpret-trailers`
+/// recognizes by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Footer {
+    pub token: String,
+    pub value: String,
+}
+
+impl Footer {
+    pub fn new(token: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+            value: value.into(),
+        }
+    }
+
+    /// Parse `Token: value` or `Token #value`; the latter keeps its `#` in `value`.
+    fn parse(line: &str) -> Option<Self> {
+        let caps = FOOTER_RE.captures(line.trim_end())?;
+        let value = match &caps["sep"] {
+            " #" => format!("#{}", &caps["value"]),
+            _ => caps["value"].trim().to_string(),
+        };
+        Some(Self::new(
+            caps["token"].replace("BREAKING-CHANGE", "BREAKING CHANGE"),
+            value,
+        ))
+    }
+}
+
+impl fmt::Display for Footer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.token, self.value)
+    }
+}
+
+/// A commit message split into its subject line, optional body and trailing footers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitMessage {
+    pub subject: String,
+    pub body: Option<String>,
+    pub footers: Vec<Footer>,
+}
+
+impl CommitMessage {
+    /// Split raw message text: the first line is the subject, a final paragraph made
+    /// only of `Token: value` lines holds the footers, and everything between is the body.
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        let (subject, rest) = text.split_once('\n').unwrap_or((text, ""));
+
+        let mut paragraphs: Vec<&str> = rest
+            .trim()
+            .split("\n\n")
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .collect();
+
+        let mut footers = Vec::new();
+        if let Some(last) = paragraphs.last() {
+            let parsed: Vec<Option<Footer>> = last.lines().map(Footer::parse).collect();
+            if parsed.iter().all(Option::is_some) {
+                footers = parsed.into_iter().flatten().collect();
+                paragraphs.pop();
+            }
+        }
+
+        let body = paragraphs.join("\n\n");
+        Self {
+            subject: subject.trim().to_string(),
+            body: (!body.is_empty()).then_some(body),
+            footers,
+        }
+    }
+
+    /// The standard message for reverting `commit_id`: `revert(scope): <description>`
+    /// with a `Refs` footer in the conventional style, git's `Revert "<subject>"` otherwise.
+    pub fn revert(subject: &str, commit_id: &str, conventional: bool) -> Self {
+        let body = Some(format!("This reverts commit {commit_id}."));
+        if !conventional {
+            return Self {
+                subject: format!("Revert \"{subject}\""),
+                body,
+                footers: Vec::new(),
+            };
+        }
+
+        let (scope, description) = match ConventionalSubject::parse(subject) {
+            Some(parsed) => (parsed.scope, parsed.description),
+            None => (None, subject.to_string()),
+        };
+        let reverted = ConventionalSubject {
+            commit_type: "revert".to_string(),
+            scope,
+            breaking: false,
+            description,
+        };
+        Self {
+            subject: reverted.to_string(),
+            body,
+            footers: vec![Footer::new("Refs", &commit_id[..commit_id.len().min(7)])],
+        }
+    }
+
+    /// Append footers from `previous` that this message does not have yet, so
+    /// trailers such as `Reviewed-by` survive a regenerated message.
+    pub fn keep_footers(&mut self, previous: &Self) {
+        for footer in &previous.footers {
+            if !self.footers.contains(footer) {
+                self.footers.push(footer.clone());
+            }
+        }
+    }
+
+    /// Re-wrap the body at `width` columns, keeping paragraphs and bullet lists intact.
+    pub fn wrap_body(&mut self, width: usize) {
+        if let Some(body) = &self.body {
+            self.body = Some(wrap_text(body, width));
+        }
+    }
+}
+
+impl fmt::Display for CommitMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.subject)?;
+        if let Some(body) = &self.body {
+            write!(f, "\n\n{body}")?;
+        }
+        if !self.footers.is_empty() {
+            writeln!(f)?;
+            for footer in &self.footers {
+                write!(f, "\n{footer}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn wrap_text(text: &str, width: usize) -> String {
+    text.split("\n\n")
+        .map(|paragraph| {
+            let mut lines = Vec::new();
+            let mut item = String::new();
+
+            // Join soft-wrapped lines; a leading `-` or `*` starts a new bullet
+            for line in paragraph.lines().map(str::trim) {
+                if line.starts_with("- ") || line.starts_with("* ") {
+                    if !item.is_empty() {
+                        lines.push(std::mem::take(&mut item));
+                    }
+                    item.push_str(line);
+                } else if !line.is_empty() {
+                    if !item.is_empty() {
+                        item.push(' ');
+                    }
+                    item.push_str(line);
+                }
+            }
+            if !item.is_empty() {
+                lines.push(item);
+            }
+
+            lines
+                .iter()
+                .map(|line| {
+                    let indent = if line.starts_with("- ") || line.starts_with("* ") {
+                        "  "
+                    } else {
+                        ""
+                    };
+                    let options = textwrap::Options::new(width)
+                        .subsequent_indent(indent)
+                        .break_words(false);
+                    textwrap::fill(line, options)
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// The header of a Conventional Commits message: `type(scope)!: description`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConventionalSubject {
+    pub commit_type: String,
+    pub scope: Option<String>,
+    pub breaking: bool,
+    pub description: String,
+}
+
+impl ConventionalSubject {
+    pub fn parse(subject: &str) -> Option<Self> {
+        let caps = CONVENTIONAL_RE.captures(subject.trim())?;
+        Some(Self {
+            commit_type: caps["type"].to_string(),
+            scope: caps
+                .name("scope")
+                .map(|m| m.as_str().trim().to_string())
+                .filter(|s| !s.is_empty()),
+            breaking: caps.name("breaking").is_some(),
+            description: caps["description"].trim().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ConventionalSubject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.commit_type)?;
+        if let Some(scope) = &self.scope {
+            write!(f, "({scope})")?;
+        }
+        if self.breaking {
+            write!(f, "!")?;
+        }
+        write!(f, ": {}", self.description)
+    }
+}
+
+/// The JSON object in a model's answer, from the first `{` to the last `}`.
+pub fn json_object(response: &str) -> Option<&str> {
+    match (response.find('{'), response.rfind('}')) {
+        (Some(start), Some(end)) if start < end => Some(&response[start..=end]),
+        _ => None,
+    }
+}
+
+/// Strip the chatter models wrap around a commit message: markdown fences,
+/// "Here is your commit message:" preambles and quotes around the subject.
+pub fn clean_response(raw: &str) -> String {
+    let mut text = raw.trim();
+
+    // Keep only the contents of the first fenced block when the model used one
+    if let Some(start) = text.find("```") {
+        let after_fence = &text[start + 3..];
+        let body_start = after_fence.find('\n').map_or(after_fence.len(), |i| i + 1);
+        let body = &after_fence[body_start..];
+        text = body.find("```").map_or(body, |end| &body[..end]);
+    }
+
+    let mut lines: Vec<&str> = text.lines().collect();
+    while lines
+        .first()
+        .is_some_and(|line| line.trim().is_empty() || PREAMBLE_RE.is_match(line.trim()))
+    {
+        lines.remove(0);
+    }
+
+    let mut message = lines.join("\n").trim().to_string();
+
+    // Unwrap a subject quoted as `feat: ...`, "feat: ..." or **feat: ...**
+    for quote in ["**", "`", "\"", "'"] {
+        if let Some(first_line) = message.lines().next()
+            && first_line.len() > 2 * quote.len()
+            && first_line.starts_with(quote)
+            && first_line.ends_with(quote)
+        {
+            let unquoted = first_line[quote.len()..first_line.len() - quote.len()].to_string();
+            message = message.replacen(first_line, &unquoted, 1);
+        }
+    }
+
+    message
+}
+
+/// Shorten `subject` to at most `max_len` characters, breaking on a word boundary.
+pub fn truncate_subject(subject: &str, max_len: usize) -> String {
+    if subject.chars().count() <= max_len {
+        return subject.to_string();
+    }
+
+    let cut: String = subject.chars().take(max_len).collect();
+    let truncated = match cut.rfind(char::is_whitespace) {
+        Some(index) if index > 0 => &cut[..index],
+        _ => cut.as_str(),
+    };
+
+    truncated
+        .trim_end_matches(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | ':' | '-'))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_lose_fences_preambles_and_quotes() {
+        assert_eq!(
+            clean_response(
+                "Here is the commit message:\n```text\nfeat(cli): add --json\n\nPrint machine-readable output.\n```\nLet me know if you want changes."
+            ),
+            "feat(cli): add --json\n\nPrint machine-readable output."
+        );
+        assert_eq!(
+            clean_response("```\nfix: handle empty ranges\n```"),
+            "fix: handle empty ranges"
+        );
+        assert_eq!(
+            clean_response("Sure! Here's a commit message:\n\n`fix: handle empty ranges`"),
+            "fix: handle empty ranges"
+        );
+        assert_eq!(
+            clean_response("**docs: explain ranges**\n\nMention `a..b`."),
+            "docs: explain ranges\n\nMention `a..b`."
+        );
+    }
+
+    #[test]
+    fn messages_split_into_subject_body_and_footers() {
+        let message = CommitMessage::parse(
+            "feat!: move the config file\n\nThe old path clashed with other tools.\n\nUsers have to move it by hand.\n\nBREAKING-CHANGE: the config lives in ~/.config/ai-commit\nRefs #12\nReviewed-by: Jane Doe <jane@example.com>",
+        );
+
+        assert_eq!(message.subject, "feat!: move the config file");
+        assert_eq!(
+            message.body.as_deref(),
+            Some("The old path clashed with other tools.\n\nUsers have to move it by hand.")
+        );
+        assert_eq!(
+            message.footers,
+            [
+                Footer::new("BREAKING CHANGE", "the config lives in ~/.config/ai-commit"),
+                Footer::new("Refs", "#12"),
+                Footer::new("Reviewed-by", "Jane Doe <jane@example.com>"),
+            ]
+        );
+        assert_eq!(
+            message.to_string(),
+            "feat!: move the config file\n\nThe old path clashed with other tools.\n\nUsers have to move it by hand.\n\nBREAKING CHANGE: the config lives in ~/.config/ai-commit\nRefs: #12\nReviewed-by: Jane Doe <jane@example.com>"
+        );
+    }
+
+    #[test]
+    fn paragraphs_that_are_not_all_footers_stay_in_the_body() {
+        let message = CommitMessage::parse(
+            "fix: keep trailers\n\nNote: this only affects amends.\nOther lines are prose.",
+        );
+        assert_eq!(
+            message.body.as_deref(),
+            Some("Note: this only affects amends.\nOther lines are prose.")
+        );
+        assert!(message.footers.is_empty());
+
+        let message = CommitMessage::parse("fix: keep trailers\n\nSigned-off-by: A <a@b.c>");
+        assert_eq!(message.body, None);
+        assert_eq!(message.footers, [Footer::new("Signed-off-by", "A <a@b.c>")]);
+
+        let message = CommitMessage::parse("  fix: subject only  \n");
+        assert_eq!(
+            message,
+            CommitMessage {
+                subject: "fix: subject only".to_string(),
+                body: None,
+                footers: Vec::new(),
+            }
+        );
+    }
+}
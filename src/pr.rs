@@ -0,0 +1,82 @@
+use std::{fmt, path::Path};
+
+use anyhow::Result;
+
+use crate::{error::AppError, message::CommitMessage, style::StyleGuide};
+
+/// Where GitHub looks for a pull request template, in order.
+const TEMPLATE_PATHS: &[&str] = &[
+    ".github/pull_request_template.md",
+    ".github/PULL_REQUEST_TEMPLATE.md",
+    "pull_request_template.md",
+    "PULL_REQUEST_TEMPLATE.md",
+    "docs/pull_request_template.md",
+    "docs/PULL_REQUEST_TEMPLATE.md",
+];
+
+/// A generated pull request title and Markdown description.
+#[derive(Debug, Clone)]
+pub struct PullRequest {
+    pub title: String,
+    pub body: String,
+}
+
+impl PullRequest {
+    /// Parse the model's answer: the title on the first line, then the description.
+    ///
+    /// A fence around the whole answer is removed, but fences inside the
+    /// description are kept. The title is fixed as the style's `on_invalid` policy allows.
+    pub fn parse(response: &str, style_guide: &StyleGuide) -> Result<Self> {
+        let mut text = response.trim();
+        if text.starts_with("```") && text.ends_with("```") && text.len() > 6 {
+            let inner = &text[3..text.len() - 3];
+            text = inner.split_once('\n').map_or("", |(_, rest)| rest).trim();
+        }
+
+        let (title, body) = text.split_once('\n').unwrap_or((text, ""));
+        let title = title
+            .trim()
+            .trim_start_matches('#')
+            .trim_start()
+            .trim_start_matches("Title:")
+            .trim()
+            .trim_matches(|c| c == '*' || c == '`' || c == '"');
+        if title.is_empty() {
+            return Err(AppError::NoResponseFromAi.into());
+        }
+
+        let mut subject = CommitMessage::parse(title);
+        style_guide.enforce(&mut subject);
+
+        Ok(Self {
+            title: subject.subject,
+            body: body.trim().to_string(),
+        })
+    }
+
+    /// Style problems of the title.
+    pub fn problems(&self, style_guide: &StyleGuide) -> Vec<String> {
+        style_guide
+            .validate(&CommitMessage::parse(&self.title))
+            .into_iter()
+            .map(|problem| format!("title: {problem}"))
+            .collect()
+    }
+}
+
+impl fmt::Display for PullRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n\n{}", self.title, self.body)
+    }
+}
+
+/// The repository's pull request template, if it has one.
+pub fn find_template(root: &Path) -> Result<Option<String>> {
+    for path in TEMPLATE_PATHS {
+        let path = root.join(path);
+        if path.is_file() {
+            return Ok(Some(std::fs::read_to_string(path)?.trim().to_string()));
+        }
+    }
+    Ok(None)
+}
@@ -1,5 +1,480 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use minijinja::Environment;
+use serde::Serialize;
+
+use crate::{
+    changelog::ChangelogEntry,
+    config::{AppConfig, PromptsConfig},
+    git::{
+        CommitInfo, DiffBase, GitRepo, HistorySample, Hunk, RepoOperation, SubmoduleUpdate,
+        UpstreamStatus,
+    },
+    pr,
+    scopes::ScopeResolver,
+    style::StyleGuide,
+    trailers,
+};
+
 pub const DEFAULT_SYSTEM_PROMPT: &str = include_str!("../prompts/system.md");
+pub const DEFAULT_USER_PROMPT: &str = include_str!("../prompts/user.md");
+pub const DEFAULT_SPLIT_PROMPT: &str = include_str!("../prompts/split.md");
+pub const DEFAULT_PR_SYSTEM_PROMPT: &str = include_str!("../prompts/pr_system.md");
+pub const DEFAULT_PR_PROMPT: &str = include_str!("../prompts/pr.md");
+pub const DEFAULT_CHANGELOG_SYSTEM_PROMPT: &str = include_str!("../prompts/changelog_system.md");
+pub const DEFAULT_CHANGELOG_PROMPT: &str = include_str!("../prompts/changelog.md");
+
+const SYSTEM_TEMPLATE: &str = "system.md";
+const USER_TEMPLATE: &str = "user.md";
+const SPLIT_TEMPLATE: &str = "split.md";
+const PR_SYSTEM_TEMPLATE: &str = "pr_system.md";
+const PR_TEMPLATE: &str = "pr.md";
+const CHANGELOG_SYSTEM_TEMPLATE: &str = "changelog_system.md";
+const CHANGELOG_TEMPLATE: &str = "changelog.md";
+const RECENT_COMMITS: usize = 5;
+/// Token budget used instead of `ai.max_tokens` when a body is requested.
+const BODY_MAX_TOKENS: u32 = 600;
+/// Token budget for a split plan, which holds several messages.
+const SPLIT_MAX_TOKENS: u32 = 2000;
+/// Token budget for a pull request title and description.
+const PR_MAX_TOKENS: u32 = 1500;
+/// Token budget for rewritten release notes.
+const CHANGELOG_MAX_TOKENS: u32 = 2000;
 
 pub fn get_system_prompt() -> String {
     DEFAULT_SYSTEM_PROMPT.to_string()
 }
+
+/// Variables available to prompt templates.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PromptContext {
+    pub diff: String,
+    pub status: String,
+    pub branch: Option<String>,
+    pub upstream: Option<UpstreamStatus>,
+    /// Merge, rebase, cherry-pick or other operation in progress.
+    pub operation: Option<RepoOperation>,
+    pub recent_commits: Vec<String>,
+    pub context: Option<String>,
+    pub language: String,
+    pub style: String,
+    pub style_instructions: String,
+    /// Whether the message should have a body below the subject.
+    pub body: bool,
+    /// Public items the diff removes, which likely break callers.
+    pub breaking_changes: Vec<String>,
+    /// Scopes derived from the staged paths, most relevant first.
+    pub scopes: Vec<String>,
+    /// Conventions learned from recent commits, absent when there are none.
+    pub history: Option<HistorySample>,
+    /// Current message of the commit being amended.
+    pub previous_message: Option<String>,
+    /// Subjects of the commits being squashed into one, oldest first.
+    pub squashed_commits: Vec<String>,
+    /// Submodules whose recorded commit changes, with the commits in between.
+    pub submodules: Vec<SubmoduleUpdate>,
+    /// Staged hunks to group into separate commits.
+    pub hunks: Vec<Hunk>,
+    /// Branch a pull request is compared against.
+    pub base_branch: Option<String>,
+    /// Full messages of the commits a pull request brings in, oldest first.
+    pub branch_commits: Vec<String>,
+    /// The repository's pull request template.
+    pub pr_template: Option<String>,
+    /// Changelog entries to rewrite as release notes.
+    pub changelog: Vec<ChangelogEntry>,
+}
+
+/// Repository conventions every prompt draws on, loaded once per command.
+#[derive(Debug)]
+pub struct PromptConventions {
+    style: StyleGuide,
+    /// Absent when `git.infer_scopes` is off or there is no working tree.
+    scopes: Option<ScopeResolver>,
+    history: Option<HistorySample>,
+}
+
+impl PromptConventions {
+    pub fn load(repo: &GitRepo, config: &AppConfig) -> Result<Self> {
+        let scopes = match repo.workdir() {
+            Some(root) if config.git.infer_scopes => Some(ScopeResolver::load(root, &config.git)?),
+            _ => None,
+        };
+        let history = repo.sample_history(config.git.history_sample)?;
+
+        Ok(Self {
+            style: StyleGuide::from_config(&config.git)?,
+            scopes,
+            history: (history.commits > 0).then_some(history),
+        })
+    }
+}
+
+impl PromptContext {
+    /// Collect the staged changes and repository details for a commit prompt.
+    ///
+    /// With [`DiffBase::HeadParent`] the diff covers HEAD's own changes as well and
+    /// HEAD's message is included, for regenerating the message of an amended commit.
+    pub fn from_repo(
+        repo: &GitRepo,
+        config: &AppConfig,
+        context: Option<&str>,
+        base: DiffBase,
+    ) -> Result<Self> {
+        let diff = repo.get_index_diff(base)?;
+        let mut prompt_context = Self::new(
+            repo,
+            config,
+            &PromptConventions::load(repo, config)?,
+            context,
+            &diff,
+            &repo.changed_paths(base)?,
+        )?;
+
+        prompt_context.status = repo.get_status_porcelain()?.trim().to_string();
+        prompt_context.operation = repo.operation();
+        prompt_context.submodules = repo.submodule_updates(base)?;
+        if base == DiffBase::HeadParent {
+            prompt_context.previous_message = Some(repo.head_message()?.trim().to_string());
+        }
+
+        Ok(prompt_context)
+    }
+
+    /// Prompt details for all uncommitted work, staged or not, as if it were committed.
+    pub fn for_worktree(
+        repo: &GitRepo,
+        config: &AppConfig,
+        context: Option<&str>,
+        untracked: bool,
+    ) -> Result<Self> {
+        let diff = repo.get_worktree_diff(untracked)?;
+        let mut prompt_context = Self::new(
+            repo,
+            config,
+            &PromptConventions::load(repo, config)?,
+            context,
+            &diff,
+            &repo.worktree_changed_paths(untracked)?,
+        )?;
+        prompt_context.status = repo.get_status_porcelain()?.trim().to_string();
+        prompt_context.operation = repo.operation();
+
+        Ok(prompt_context)
+    }
+
+    /// Prompt details for rewording an existing commit from its own diff and message.
+    ///
+    /// Takes the `conventions` from the caller, which loads them once for all commits.
+    pub fn for_commit(
+        repo: &GitRepo,
+        config: &AppConfig,
+        conventions: &PromptConventions,
+        context: Option<&str>,
+        commit: &CommitInfo,
+    ) -> Result<Self> {
+        let diff = repo.get_commit_diff(commit.id)?;
+        let mut prompt_context = Self::new(
+            repo,
+            config,
+            conventions,
+            context,
+            &diff,
+            &repo.commit_changed_paths(commit.id)?,
+        )?;
+        prompt_context.previous_message = Some(commit.message.trim().to_string());
+        prompt_context.submodules = repo.commit_submodule_updates(commit.id)?;
+
+        Ok(prompt_context)
+    }
+
+    /// Prompt details for one message summarising `commits` (oldest first) from
+    /// their combined diff and individual subjects.
+    pub fn for_squash(
+        repo: &GitRepo,
+        config: &AppConfig,
+        context: Option<&str>,
+        commits: &[CommitInfo],
+    ) -> Result<Self> {
+        let (Some(first), Some(last)) = (commits.first(), commits.last()) else {
+            return Self::new(
+                repo,
+                config,
+                &PromptConventions::load(repo, config)?,
+                context,
+                "",
+                &[],
+            );
+        };
+        let base = repo.parent_id(first.id)?;
+
+        let diff = repo.get_range_diff(base, last.id)?;
+        let paths = repo.range_changed_paths(base, last.id)?;
+        let mut prompt_context = Self::new(
+            repo,
+            config,
+            &PromptConventions::load(repo, config)?,
+            context,
+            &diff,
+            &paths,
+        )?;
+        prompt_context.submodules = repo.range_submodule_updates(base, last.id)?;
+        prompt_context.squashed_commits = commits
+            .iter()
+            .map(|commit| commit.summary().to_string())
+            .collect();
+        prompt_context.body = true;
+
+        Ok(prompt_context)
+    }
+
+    /// Prompt details for grouping `hunks` of the staged changes into commits.
+    pub fn for_split(
+        repo: &GitRepo,
+        config: &AppConfig,
+        context: Option<&str>,
+        hunks: Vec<Hunk>,
+    ) -> Result<Self> {
+        let diff = repo.get_index_diff(DiffBase::Head)?;
+        let mut prompt_context = Self::new(
+            repo,
+            config,
+            &PromptConventions::load(repo, config)?,
+            context,
+            &diff,
+            &repo.changed_paths(DiffBase::Head)?,
+        )?;
+        // The hunks already carry the changes
+        prompt_context.diff.clear();
+        prompt_context.hunks = hunks;
+
+        Ok(prompt_context)
+    }
+
+    /// Prompt details for a pull request bringing `commits` (oldest first) from
+    /// `merge_base` into `base`.
+    pub fn for_pull_request(
+        repo: &GitRepo,
+        config: &AppConfig,
+        context: Option<&str>,
+        base: &str,
+        merge_base: git2::Oid,
+        commits: &[CommitInfo],
+    ) -> Result<Self> {
+        let head = repo.head_id()?;
+        let diff = repo.get_range_diff(Some(merge_base), head)?;
+        let paths = repo.range_changed_paths(Some(merge_base), head)?;
+        let mut prompt_context = Self::new(
+            repo,
+            config,
+            &PromptConventions::load(repo, config)?,
+            context,
+            &diff,
+            &paths,
+        )?;
+        prompt_context.submodules = repo.range_submodule_updates(Some(merge_base), head)?;
+        prompt_context.base_branch = Some(base.to_string());
+        prompt_context.branch_commits = commits
+            .iter()
+            .map(|commit| commit.message.trim().to_string())
+            .collect();
+        prompt_context.pr_template = match repo.workdir() {
+            Some(root) => pr::find_template(root)?,
+            None => None,
+        };
+
+        Ok(prompt_context)
+    }
+
+    /// Prompt details for rewriting changelog `entries` into release notes.
+    pub fn for_changelog(
+        repo: &GitRepo,
+        config: &AppConfig,
+        context: Option<&str>,
+        entries: Vec<ChangelogEntry>,
+    ) -> Result<Self> {
+        let mut prompt_context = Self::new(
+            repo,
+            config,
+            &PromptConventions::load(repo, config)?,
+            context,
+            "",
+            &[],
+        )?;
+        prompt_context.changelog = entries;
+
+        Ok(prompt_context)
+    }
+
+    fn new(
+        repo: &GitRepo,
+        config: &AppConfig,
+        conventions: &PromptConventions,
+        context: Option<&str>,
+        diff: &str,
+        paths: &[String],
+    ) -> Result<Self> {
+        let diff = diff.trim().to_string();
+        let changed_lines = diff
+            .lines()
+            .filter(|line| line.starts_with('+') || line.starts_with('-'))
+            .count();
+
+        let scopes = conventions.scopes.as_ref().map_or_else(Vec::new, |scopes| {
+            scopes.candidates(paths.iter().map(String::as_str))
+        });
+
+        Ok(Self {
+            history: conventions.history.clone(),
+            scopes,
+            body: config
+                .git
+                .body
+                .includes_body(changed_lines, config.git.body_threshold),
+            breaking_changes: trailers::removed_public_items(&diff),
+            diff,
+            branch: repo.current_branch()?,
+            upstream: repo.upstream_status()?,
+            recent_commits: repo.recent_commit_subjects(RECENT_COMMITS)?,
+            context: context.map(ToString::to_string),
+            language: config.prompts.language.clone(),
+            style: conventions.style.style.to_string(),
+            style_instructions: conventions.style.instructions(),
+            ..Self::default()
+        })
+    }
+}
+
+/// What a prompt asks the model for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PromptKind {
+    Commit,
+    Split,
+    PullRequest,
+    Changelog,
+}
+
+/// Fully rendered messages ready to send to a provider.
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub system: String,
+    pub user: String,
+    /// Overrides `ai.max_tokens` when the answer needs more room.
+    pub max_tokens: Option<u32>,
+}
+
+/// Prompt templates, resolved from the repository, the config directory,
+/// `config.toml` and the built-in defaults, in that order.
+#[derive(Debug, Clone)]
+pub struct PromptTemplates {
+    pub system: String,
+    pub user: String,
+    pub split: String,
+    pub pr_system: String,
+    pub pr: String,
+    pub changelog_system: String,
+    pub changelog: String,
+    pub sources: Vec<PathBuf>,
+}
+
+impl PromptTemplates {
+    pub fn load(config: &PromptsConfig, repo_root: Option<&Path>) -> Result<Self> {
+        let mut dirs = Vec::new();
+        if let Some(root) = repo_root {
+            dirs.push(root.join(".ai-commit").join("prompts"));
+        }
+        dirs.push(AppConfig::config_dir()?.join("prompts"));
+
+        let mut sources = Vec::new();
+        let mut read_template = |name: &str| -> Result<Option<String>> {
+            for dir in &dirs {
+                let path = dir.join(name);
+                if path.is_file() {
+                    let content = std::fs::read_to_string(&path)?;
+                    sources.push(path);
+                    return Ok(Some(content));
+                }
+            }
+            Ok(None)
+        };
+
+        let system =
+            read_template(SYSTEM_TEMPLATE)?.unwrap_or_else(|| config.system_prompt.clone());
+        let user = read_template(USER_TEMPLATE)?
+            .or_else(|| config.user_prompt.clone())
+            .unwrap_or_else(|| DEFAULT_USER_PROMPT.to_string());
+        let split =
+            read_template(SPLIT_TEMPLATE)?.unwrap_or_else(|| DEFAULT_SPLIT_PROMPT.to_string());
+        let pr_system = read_template(PR_SYSTEM_TEMPLATE)?
+            .unwrap_or_else(|| DEFAULT_PR_SYSTEM_PROMPT.to_string());
+        let pr = read_template(PR_TEMPLATE)?.unwrap_or_else(|| DEFAULT_PR_PROMPT.to_string());
+        let changelog_system = read_template(CHANGELOG_SYSTEM_TEMPLATE)?
+            .unwrap_or_else(|| DEFAULT_CHANGELOG_SYSTEM_PROMPT.to_string());
+        let changelog = read_template(CHANGELOG_TEMPLATE)?
+            .unwrap_or_else(|| DEFAULT_CHANGELOG_PROMPT.to_string());
+
+        Ok(Self {
+            system,
+            user,
+            split,
+            pr_system,
+            pr,
+            changelog_system,
+            changelog,
+            sources,
+        })
+    }
+
+    pub fn render(&self, context: &PromptContext) -> Result<Prompt> {
+        self.render_template(PromptKind::Commit, context)
+    }
+
+    /// Render the prompt asking for a split plan of `context.hunks`.
+    pub fn render_split(&self, context: &PromptContext) -> Result<Prompt> {
+        self.render_template(PromptKind::Split, context)
+    }
+
+    /// Render the prompt asking for a pull request title and description.
+    pub fn render_pr(&self, context: &PromptContext) -> Result<Prompt> {
+        self.render_template(PromptKind::PullRequest, context)
+    }
+
+    /// Render the prompt asking for release notes for `context.changelog`.
+    pub fn render_changelog(&self, context: &PromptContext) -> Result<Prompt> {
+        self.render_template(PromptKind::Changelog, context)
+    }
+
+    fn render_template(&self, kind: PromptKind, context: &PromptContext) -> Result<Prompt> {
+        let (system_name, system) = match kind {
+            PromptKind::Commit | PromptKind::Split => (SYSTEM_TEMPLATE, &self.system),
+            PromptKind::PullRequest => (PR_SYSTEM_TEMPLATE, &self.pr_system),
+            PromptKind::Changelog => (CHANGELOG_SYSTEM_TEMPLATE, &self.changelog_system),
+        };
+        let (user_name, user, max_tokens) = match kind {
+            PromptKind::Commit => (
+                USER_TEMPLATE,
+                &self.user,
+                context.body.then_some(BODY_MAX_TOKENS),
+            ),
+            PromptKind::Split => (SPLIT_TEMPLATE, &self.split, Some(SPLIT_MAX_TOKENS)),
+            PromptKind::PullRequest => (PR_TEMPLATE, &self.pr, Some(PR_MAX_TOKENS)),
+            PromptKind::Changelog => (
+                CHANGELOG_TEMPLATE,
+                &self.changelog,
+                Some(CHANGELOG_MAX_TOKENS),
+            ),
+        };
+
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.add_template(system_name, system)?;
+        env.add_template(user_name, user)?;
+
+        Ok(Prompt {
+            system: env.get_template(system_name)?.render(context)?,
+            user: env.get_template(user_name)?.render(context)?,
+            max_tokens,
+        })
+    }
+}
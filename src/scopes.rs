@@ -0,0 +1,440 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{config::GitConfig, error::AppError};
+
+/// Maps paths matching `pattern` to `scope`, stored as `[[git.scope_rules]]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScopeRule {
+    pub pattern: String,
+    pub scope: String,
+}
+
+/// A package of a Cargo or npm workspace and the directory it lives in.
+#[derive(Debug, Clone)]
+struct WorkspacePackage {
+    dir: PathBuf,
+    name: String,
+}
+
+/// Derives commit scopes from changed paths using, in order, configured glob
+/// rules, Cargo workspace members, `package.json` workspaces and top-level directories.
+#[derive(Debug)]
+pub struct ScopeResolver {
+    rules: Vec<(GlobMatcher, String)>,
+    packages: Vec<WorkspacePackage>,
+    allowed: Vec<String>,
+}
+
+impl ScopeResolver {
+    pub fn load(root: &Path, config: &GitConfig) -> Result<Self> {
+        let rules = config
+            .scope_rules
+            .iter()
+            .map(|rule| {
+                let glob = Glob::new(&rule.pattern)
+                    .map_err(|e| AppError::InvalidScopeRule(format!("`{}`: {e}", rule.pattern)))?;
+                Ok((glob.compile_matcher(), rule.scope.clone()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut packages = cargo_workspace_members(root)?;
+        packages.extend(npm_workspace_packages(root)?);
+        // Prefer the most specific package when workspaces nest
+        packages.sort_by_key(|package| std::cmp::Reverse(package.dir.components().count()));
+        debug!("Found {} workspace packages", packages.len());
+
+        Ok(Self {
+            rules,
+            packages,
+            allowed: config.commit_scopes.clone(),
+        })
+    }
+
+    /// Scope for a single path relative to the repository root.
+    pub fn resolve(&self, path: &str) -> Option<String> {
+        let path = Path::new(path);
+
+        let scope = self
+            .rules
+            .iter()
+            .find(|(matcher, _)| matcher.is_match(path))
+            .map(|(_, scope)| scope.clone())
+            .or_else(|| {
+                self.packages
+                    .iter()
+                    .find(|package| path.starts_with(&package.dir))
+                    .map(|package| package.name.clone())
+            })
+            .or_else(|| top_level_dir(path))?;
+
+        (self.allowed.is_empty() || self.allowed.contains(&scope)).then_some(scope)
+    }
+
+    /// Distinct scopes for `paths`, most frequent first.
+    pub fn candidates<'a>(&self, paths: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for scope in paths.into_iter().filter_map(|path| self.resolve(path)) {
+            *counts.entry(scope).or_default() += 1;
+        }
+
+        let mut scopes: Vec<(String, usize)> = counts.into_iter().collect();
+        scopes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scopes.into_iter().map(|(scope, _)| scope).collect()
+    }
+}
+
+fn top_level_dir(path: &Path) -> Option<String> {
+    let mut components = path.components();
+    let first = components.next()?;
+    // Files in the repository root have no meaningful scope
+    components.next()?;
+    let name = first.as_os_str().to_str()?;
+    (!name.starts_with('.')).then(|| name.to_lowercase())
+}
+
+/// Build a matcher for workspace member globs such as `crates/*`.
+fn member_globs<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+        // Workspace globs follow gitignore rules: `*` does not cross directories
+        builder.add(
+            GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| AppError::InvalidScopeRule(format!("workspace `{pattern}`: {e}")))?,
+        );
+    }
+    Ok(builder.build()?)
+}
+
+/// Directories below `root` (up to `depth` levels) whose relative path matches `globs`
+/// and that contain `manifest`.
+fn matching_dirs(root: &Path, globs: &GlobSet, manifest: &str, depth: usize) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut pending = vec![(PathBuf::new(), 0)];
+
+    while let Some((relative, level)) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(root.join(&relative)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            if !entry.path().is_dir()
+                || name
+                    .to_str()
+                    .is_none_or(|n| n.starts_with('.') || n == "node_modules")
+            {
+                continue;
+            }
+            let child = relative.join(&name);
+            if globs.is_match(&child) && root.join(&child).join(manifest).is_file() {
+                found.push(child.clone());
+            }
+            if level + 1 < depth {
+                pending.push((child, level + 1));
+            }
+        }
+    }
+
+    found
+}
+
+#[derive(Deserialize)]
+struct CargoManifest {
+    package: Option<CargoPackage>,
+    workspace: Option<CargoWorkspace>,
+}
+
+#[derive(Deserialize)]
+struct CargoPackage {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct CargoWorkspace {
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+fn cargo_workspace_members(root: &Path) -> Result<Vec<WorkspacePackage>> {
+    let Some(workspace) = read_cargo_manifest(root)?.and_then(|manifest| manifest.workspace) else {
+        return Ok(Vec::new());
+    };
+
+    let globs = member_globs(workspace.members.iter().map(String::as_str))?;
+    let mut packages = Vec::new();
+    for dir in matching_dirs(root, &globs, "Cargo.toml", 3) {
+        if let Some(package) = read_cargo_manifest(&root.join(&dir))?.and_then(|m| m.package) {
+            packages.push(WorkspacePackage {
+                dir,
+                name: package.name,
+            });
+        }
+    }
+
+    Ok(packages)
+}
+
+fn read_cargo_manifest(dir: &Path) -> Result<Option<CargoManifest>> {
+    let path = dir.join("Cargo.toml");
+    if !path.is_file() {
+        return Ok(None);
+    }
+    Ok(toml::from_str(&std::fs::read_to_string(path)?).ok())
+}
+
+#[derive(Deserialize)]
+struct PackageJson {
+    name: Option<String>,
+    workspaces: Option<NpmWorkspaces>,
+}
+
+/// `"workspaces": [...]` or Yarn's `"workspaces": { "packages": [...] }`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NpmWorkspaces {
+    List(Vec<String>),
+    Object { packages: Vec<String> },
+}
+
+fn npm_workspace_packages(root: &Path) -> Result<Vec<WorkspacePackage>> {
+    let patterns = match read_package_json(root)?.and_then(|package| package.workspaces) {
+        Some(NpmWorkspaces::List(patterns) | NpmWorkspaces::Object { packages: patterns }) => {
+            patterns
+        }
+        None => return Ok(Vec::new()),
+    };
+
+    let globs = member_globs(patterns.iter().map(String::as_str))?;
+    let mut packages = Vec::new();
+    for dir in matching_dirs(root, &globs, "package.json", 3) {
+        if let Some(name) = read_package_json(&root.join(&dir))?.and_then(|p| p.name) {
+            // `@org/web` becomes `web`
+            let name = name.rsplit('/').next().unwrap_or(&name).to_string();
+            packages.push(WorkspacePackage { dir, name });
+        }
+    }
+
+    Ok(packages)
+}
+
+fn read_package_json(dir: &Path) -> Result<Option<PackageJson>> {
+    let path = dir.join("package.json");
+    if !path.is_file() {
+        return Ok(None);
+    }
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::config::AppConfig;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().expect("file has a parent")).expect("create dirs");
+        fs::write(path, content).expect("write file");
+    }
+
+    fn resolver(root: &Path, rules: &[(&str, &str)], allowed: &[&str]) -> ScopeResolver {
+        let mut config = AppConfig::default().git;
+        config.scope_rules = rules
+            .iter()
+            .map(|(pattern, scope)| ScopeRule {
+                pattern: (*pattern).to_string(),
+                scope: (*scope).to_string(),
+            })
+            .collect();
+        config.commit_scopes = allowed.iter().map(ToString::to_string).collect();
+        ScopeResolver::load(root, &config).expect("load scopes")
+    }
+
+    /// A Cargo workspace with `crates/*` members, one of them nested in another
+    /// directory level, and a directory under `crates/` that is not a package.
+    fn cargo_workspace() -> TempDir {
+        let dir = TempDir::new().expect("temp dir");
+        let root = dir.path();
+        write(
+            root,
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\", \"tools/gen\"]\n",
+        );
+        write(
+            root,
+            "crates/core/Cargo.toml",
+            "[package]\nname = \"app-core\"\n",
+        );
+        write(
+            root,
+            "crates/cli/Cargo.toml",
+            "[package]\nname = \"app-cli\"\n",
+        );
+        write(root, "crates/notes/README.md", "not a package\n");
+        write(
+            root,
+            "tools/gen/Cargo.toml",
+            "[package]\nname = \"codegen\"\n",
+        );
+        write(
+            root,
+            "tools/other/Cargo.toml",
+            "[package]\nname = \"not-a-member\"\n",
+        );
+        dir
+    }
+
+    #[test]
+    fn cargo_workspace_members_name_their_files() {
+        let dir = cargo_workspace();
+        let scopes = resolver(dir.path(), &[], &[]);
+
+        assert_eq!(
+            scopes.resolve("crates/core/src/lib.rs").as_deref(),
+            Some("app-core")
+        );
+        assert_eq!(
+            scopes.resolve("crates/cli/Cargo.toml").as_deref(),
+            Some("app-cli")
+        );
+        assert_eq!(
+            scopes.resolve("tools/gen/src/main.rs").as_deref(),
+            Some("codegen")
+        );
+        // Not members, so the top-level directory is used
+        assert_eq!(
+            scopes.resolve("crates/notes/README.md").as_deref(),
+            Some("crates")
+        );
+        assert_eq!(
+            scopes.resolve("tools/other/src/main.rs").as_deref(),
+            Some("tools")
+        );
+    }
+
+    #[test]
+    fn npm_workspace_packages_name_their_files() {
+        let dir = TempDir::new().expect("temp dir");
+        let root = dir.path();
+        write(
+            root,
+            "package.json",
+            r#"{"name": "root", "workspaces": ["packages/*"]}"#,
+        );
+        write(root, "packages/web/package.json", r#"{"name": "@org/web"}"#);
+        write(root, "packages/api/package.json", r#"{"name": "api"}"#);
+        write(
+            root,
+            "packages/web/node_modules/dep/package.json",
+            r#"{"name": "dep"}"#,
+        );
+        let scopes = resolver(root, &[], &[]);
+
+        assert_eq!(
+            scopes.resolve("packages/web/src/App.tsx").as_deref(),
+            Some("web")
+        );
+        assert_eq!(
+            scopes.resolve("packages/api/index.js").as_deref(),
+            Some("api")
+        );
+
+        // Yarn's object form lists the same globs under `packages`
+        write(
+            root,
+            "package.json",
+            r#"{"workspaces": {"packages": ["packages/*"]}}"#,
+        );
+        let scopes = resolver(root, &[], &[]);
+        assert_eq!(
+            scopes.resolve("packages/api/index.js").as_deref(),
+            Some("api")
+        );
+    }
+
+    #[test]
+    fn top_level_directories_are_the_fallback() {
+        let dir = TempDir::new().expect("temp dir");
+        let scopes = resolver(dir.path(), &[], &[]);
+
+        assert_eq!(scopes.resolve("src/main.rs").as_deref(), Some("src"));
+        assert_eq!(scopes.resolve("Docs/guide.md").as_deref(), Some("docs"));
+        assert_eq!(scopes.resolve("README.md"), None);
+        assert_eq!(scopes.resolve(".github/workflows/ci.yml"), None);
+    }
+
+    #[test]
+    fn rules_win_over_packages_and_packages_over_directories() {
+        let dir = cargo_workspace();
+        let scopes = resolver(
+            dir.path(),
+            &[("crates/core/src/git/**", "git"), ("**/*.md", "docs")],
+            &[],
+        );
+
+        assert_eq!(
+            scopes.resolve("crates/core/src/git/mod.rs").as_deref(),
+            Some("git")
+        );
+        assert_eq!(
+            scopes.resolve("crates/core/README.md").as_deref(),
+            Some("docs")
+        );
+        assert_eq!(scopes.resolve("README.md").as_deref(), Some("docs"));
+        assert_eq!(
+            scopes.resolve("crates/core/src/lib.rs").as_deref(),
+            Some("app-core")
+        );
+        assert_eq!(
+            scopes.resolve("scripts/release.sh").as_deref(),
+            Some("scripts")
+        );
+    }
+
+    #[test]
+    fn candidates_are_allowed_scopes_by_frequency() {
+        let dir = cargo_workspace();
+        let paths = [
+            "crates/cli/src/main.rs",
+            "crates/core/src/lib.rs",
+            "crates/core/src/git.rs",
+            "scripts/release.sh",
+        ];
+
+        let scopes = resolver(dir.path(), &[], &[]);
+        assert_eq!(scopes.candidates(paths), ["app-core", "app-cli", "scripts"]);
+
+        let scopes = resolver(dir.path(), &[], &["app-cli", "scripts"]);
+        assert_eq!(scopes.candidates(paths), ["app-cli", "scripts"]);
+        assert_eq!(scopes.resolve("crates/core/src/lib.rs"), None);
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        let dir = TempDir::new().expect("temp dir");
+        let mut config = AppConfig::default().git;
+        config.scope_rules = vec![ScopeRule {
+            pattern: "src/[".to_string(),
+            scope: "broken".to_string(),
+        }];
+
+        let error = ScopeResolver::load(dir.path(), &config).expect_err("invalid glob");
+        assert!(matches!(
+            error.downcast_ref::<AppError>(),
+            Some(AppError::InvalidScopeRule(_))
+        ));
+    }
+}
@@ -0,0 +1,155 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use age::secrecy::SecretString;
+use anyhow::Result;
+use dialoguer::Password;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{config::AppConfig, error::AppError};
+
+const KEYRING_SERVICE: &str = "ai-commit";
+const PASSPHRASE_ENV: &str = "AI_COMMIT_PASSPHRASE";
+
+/// Where API keys are persisted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretBackend {
+    /// Use the OS keyring when reachable, otherwise the encrypted file.
+    #[default]
+    Auto,
+    Keyring,
+    File,
+}
+
+impl std::fmt::Display for SecretBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Keyring => write!(f, "keyring"),
+            Self::File => write!(f, "file"),
+        }
+    }
+}
+
+/// Provider credentials kept in the OS keyring or a passphrase-encrypted file.
+pub struct SecretStore {
+    backend: SecretBackend,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CredentialsFile {
+    #[serde(default)]
+    api_keys: BTreeMap<String, String>,
+}
+
+impl SecretStore {
+    pub fn new(backend: SecretBackend) -> Self {
+        Self { backend }
+    }
+
+    pub fn credentials_path() -> Result<PathBuf> {
+        Ok(AppConfig::config_dir()?.join("credentials.age"))
+    }
+
+    /// Store the API key for `provider`, returning the backend that accepted it.
+    pub fn set_api_key(&self, provider: &str, api_key: &str) -> Result<SecretBackend> {
+        if self.backend != SecretBackend::File {
+            match keyring_entry(provider).and_then(|entry| Ok(entry.set_password(api_key)?)) {
+                Ok(()) => return Ok(SecretBackend::Keyring),
+                Err(e) if self.backend == SecretBackend::Keyring => return Err(e),
+                Err(e) => debug!("Keyring unavailable, using encrypted file: {}", e),
+            }
+        }
+
+        let passphrase = read_passphrase(true)?;
+        let mut credentials = Self::read_file(&passphrase)?.unwrap_or_default();
+        credentials
+            .api_keys
+            .insert(provider.to_string(), api_key.to_string());
+        Self::write_file(&credentials, passphrase)?;
+
+        Ok(SecretBackend::File)
+    }
+
+    /// Look up the API key for `provider`, returning `None` when nothing is stored.
+    pub fn get_api_key(&self, provider: &str) -> Result<Option<String>> {
+        if self.backend != SecretBackend::File {
+            match keyring_entry(provider).and_then(|entry| Ok(entry.get_password()?)) {
+                Ok(api_key) => return Ok(Some(api_key)),
+                Err(e) if self.backend == SecretBackend::Keyring => {
+                    return match e.downcast_ref::<keyring::Error>() {
+                        Some(keyring::Error::NoEntry) => Ok(None),
+                        _ => Err(e),
+                    };
+                }
+                Err(e) => debug!("No keyring entry for {}: {}", provider, e),
+            }
+        }
+
+        if !Self::credentials_path()?.exists() {
+            return Ok(None);
+        }
+
+        let passphrase = read_passphrase(false)?;
+        Ok(Self::read_file(&passphrase)?.and_then(|mut c| c.api_keys.remove(provider)))
+    }
+
+    fn read_file(passphrase: &SecretString) -> Result<Option<CredentialsFile>> {
+        let path = Self::credentials_path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let encrypted = std::fs::read(path)?;
+        let identity = age::scrypt::Identity::new(passphrase.clone());
+        let decrypted = age::decrypt(&identity, &encrypted)
+            .map_err(|e| AppError::SecretStore(format!("failed to decrypt credentials: {e}")))?;
+        let content = String::from_utf8(decrypted)
+            .map_err(|e| AppError::SecretStore(format!("corrupt credentials file: {e}")))?;
+
+        Ok(Some(toml::from_str(&content)?))
+    }
+
+    fn write_file(credentials: &CredentialsFile, passphrase: SecretString) -> Result<()> {
+        let config_dir = AppConfig::config_dir()?;
+        std::fs::create_dir_all(&config_dir)?;
+
+        let content = toml::to_string_pretty(credentials)?;
+        let recipient = age::scrypt::Recipient::new(passphrase);
+        let encrypted = age::encrypt(&recipient, content.as_bytes())
+            .map_err(|e| AppError::SecretStore(format!("failed to encrypt credentials: {e}")))?;
+
+        std::fs::write(Self::credentials_path()?, encrypted)?;
+        Ok(())
+    }
+}
+
+/// Mask a secret for display, keeping only a short prefix and suffix.
+pub fn mask(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() <= 8 {
+        return "*".repeat(chars.len());
+    }
+
+    let prefix: String = chars[..3].iter().collect();
+    let suffix: String = chars[chars.len() - 4..].iter().collect();
+    format!("{prefix}...{suffix}")
+}
+
+fn keyring_entry(provider: &str) -> Result<keyring::Entry> {
+    Ok(keyring::Entry::new(KEYRING_SERVICE, provider)?)
+}
+
+fn read_passphrase(confirm: bool) -> Result<SecretString> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(SecretString::from(passphrase));
+    }
+
+    let mut prompt = Password::new().with_prompt("Credentials file passphrase");
+    if confirm {
+        prompt = prompt.with_confirmation("Confirm passphrase", "Passphrases do not match");
+    }
+
+    Ok(SecretString::from(prompt.interact()?))
+}
@@ -0,0 +1,86 @@
+use std::path::Path;
+
+use anyhow::Result;
+use globset::{GlobBuilder, GlobMatcher};
+
+/// One line of `.git/info/sparse-checkout`, in gitignore syntax.
+#[derive(Debug)]
+struct SparsePattern {
+    matcher: GlobMatcher,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// The paths a sparse checkout keeps in the working tree, read from
+/// `info/sparse-checkout`. Cone mode patterns are plain gitignore patterns too.
+#[derive(Debug)]
+pub struct SparseCheckout {
+    patterns: Vec<SparsePattern>,
+}
+
+impl SparseCheckout {
+    /// Load the patterns from `git_dir`, or `None` when there is no pattern file.
+    pub fn load(git_dir: &Path) -> Result<Option<Self>> {
+        let path = git_dir.join("info").join("sparse-checkout");
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let mut patterns = Vec::new();
+        for line in std::fs::read_to_string(path)?.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            // Patterns without an inner slash match at any depth
+            let glob = match line.strip_prefix('/') {
+                Some(anchored) => anchored.to_string(),
+                None if line.contains('/') => line.to_string(),
+                None => format!("**/{line}"),
+            };
+            let Ok(glob) = GlobBuilder::new(&glob).literal_separator(true).build() else {
+                continue;
+            };
+            patterns.push(SparsePattern {
+                matcher: glob.compile_matcher(),
+                negated,
+                dir_only,
+            });
+        }
+
+        Ok(Some(Self { patterns }))
+    }
+
+    /// Whether `path` (a file relative to the repository root) is inside the checkout.
+    ///
+    /// Like git, the last pattern matching the path decides; without one the
+    /// nearest parent directory with a match decides.
+    pub fn contains(&self, path: &str) -> bool {
+        let mut current = Some(Path::new(path.trim_end_matches('/')));
+        let mut is_dir = path.ends_with('/');
+        while let Some(candidate) = current.filter(|p| !p.as_os_str().is_empty()) {
+            let decision = self
+                .patterns
+                .iter()
+                .rev()
+                .find(|pattern| {
+                    (is_dir || !pattern.dir_only) && pattern.matcher.is_match(candidate)
+                })
+                .map(|pattern| !pattern.negated);
+            if let Some(included) = decision {
+                return included;
+            }
+            current = candidate.parent();
+            is_dir = true;
+        }
+        false
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::Result;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    error::AppError,
+    git::Hunk,
+    message::{self, BODY_WIDTH, CommitMessage},
+    style::StyleGuide,
+};
+
+/// One commit of a split plan: the ids of its hunks and its message.
+#[derive(Debug, Clone)]
+pub struct PlannedCommit {
+    pub hunks: Vec<String>,
+    pub message: CommitMessage,
+}
+
+/// How the model proposes to split the staged hunks into commits, in commit order.
+#[derive(Debug, Clone)]
+pub struct SplitPlan {
+    pub commits: Vec<PlannedCommit>,
+}
+
+#[derive(Deserialize)]
+struct RawPlan {
+    commits: Vec<RawCommit>,
+}
+
+#[derive(Deserialize)]
+struct RawCommit {
+    hunks: Vec<String>,
+    message: String,
+}
+
+impl SplitPlan {
+    /// Parse the model's JSON answer, ignoring chatter around the object.
+    ///
+    /// Unknown and repeated hunk ids are dropped, and hunks the model left out are
+    /// added to the last commit so nothing staged is lost. Messages are fixed as the
+    /// style's `on_invalid` policy allows.
+    pub fn parse(response: &str, hunks: &[Hunk], style_guide: &StyleGuide) -> Result<Self> {
+        let Some(json) = message::json_object(response) else {
+            return Err(AppError::InvalidSplitPlan("no JSON object in the answer".into()).into());
+        };
+        let raw: RawPlan =
+            serde_json::from_str(json).map_err(|e| AppError::InvalidSplitPlan(e.to_string()))?;
+
+        let mut assigned: Vec<&str> = Vec::new();
+        let mut commits = Vec::new();
+        for raw_commit in raw.commits {
+            let mut ids = Vec::new();
+            for id in raw_commit.hunks {
+                let Some(hunk) = hunks.iter().find(|hunk| hunk.id == id) else {
+                    warn!("Split plan names unknown hunk {}", id);
+                    continue;
+                };
+                if assigned.contains(&hunk.id.as_str()) {
+                    warn!("Split plan assigns hunk {} twice", id);
+                    continue;
+                }
+                assigned.push(&hunk.id);
+                ids.push(id);
+            }
+            if ids.is_empty() {
+                continue;
+            }
+
+            let mut message = CommitMessage::parse(&message::clean_response(&raw_commit.message));
+            message.wrap_body(BODY_WIDTH);
+            style_guide.enforce(&mut message);
+            commits.push(PlannedCommit {
+                hunks: ids,
+                message,
+            });
+        }
+
+        let missing: Vec<String> = hunks
+            .iter()
+            .filter(|hunk| !assigned.contains(&hunk.id.as_str()))
+            .map(|hunk| hunk.id.clone())
+            .collect();
+        let Some(last) = commits.last_mut() else {
+            return Err(AppError::InvalidSplitPlan("no commits planned".into()).into());
+        };
+        if !missing.is_empty() {
+            warn!(
+                "Split plan leaves out hunks {}, adding them to the last commit",
+                missing.join(", ")
+            );
+            last.hunks.extend(missing);
+        }
+
+        Ok(Self { commits })
+    }
+
+    /// Style problems of every planned message, prefixed with its commit number.
+    pub fn problems(&self, style_guide: &StyleGuide) -> Vec<String> {
+        self.commits
+            .iter()
+            .enumerate()
+            .flat_map(|(index, commit)| {
+                style_guide
+                    .validate(&commit.message)
+                    .into_iter()
+                    .map(move |problem| format!("commit {}: {problem}", index + 1))
+            })
+            .collect()
+    }
+}
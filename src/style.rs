@@ -0,0 +1,484 @@
+use std::sync::LazyLock;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::GitConfig,
+    error::AppError,
+    message::{CommitMessage, ConventionalSubject, truncate_subject},
+};
+
+const CONVENTIONAL_PROMPT: &str = include_str!("../prompts/styles/conventional.md");
+const GITMOJI_PROMPT: &str = include_str!("../prompts/styles/gitmoji.md");
+const PLAIN_PROMPT: &str = include_str!("../prompts/styles/plain.md");
+const KERNEL_PROMPT: &str = include_str!("../prompts/styles/kernel.md");
+
+pub const CONVENTIONAL_TYPES: &[&str] = &[
+    "feat", "fix", "docs", "style", "refactor", "perf", "test", "build", "ci", "chore", "revert",
+];
+
+/// Types models commonly invent, mapped to their Conventional Commits spelling.
+const TYPE_ALIASES: &[(&str, &str)] = &[
+    ("feature", "feat"),
+    ("features", "feat"),
+    ("add", "feat"),
+    ("bugfix", "fix"),
+    ("hotfix", "fix"),
+    ("bug", "fix"),
+    ("doc", "docs"),
+    ("documentation", "docs"),
+    ("tests", "test"),
+    ("testing", "test"),
+    ("refactoring", "refactor"),
+    ("performance", "perf"),
+    ("chores", "chore"),
+    ("deps", "build"),
+    ("styles", "style"),
+];
+static GITMOJI_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(:[a-z0-9_+-]+:|\p{Extended_Pictographic}[\u{FE0F}\u{200D}\p{Extended_Pictographic}]*) \S")
+        .expect("valid gitmoji regex")
+});
+static KERNEL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[a-z0-9_.\-/]+(, ?[a-z0-9_.\-/]+)*: [a-z0-9]").expect("valid kernel regex")
+});
+static PREFIX_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\w+(\([^)]*\))?!?:").expect("valid prefix regex"));
+
+/// Commit message convention the generated subject must follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageStyle {
+    /// `type(scope): description`
+    Conventional,
+    /// `<emoji> Description`
+    Gitmoji,
+    /// `Description` in imperative mood without a prefix
+    Plain,
+    /// Linux kernel `subsystem: summary`
+    Kernel,
+    /// Prompt and pattern from `git.custom_style_prompt` / `git.custom_style_pattern`
+    Custom,
+}
+
+impl std::fmt::Display for MessageStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Conventional => write!(f, "conventional"),
+            Self::Gitmoji => write!(f, "gitmoji"),
+            Self::Plain => write!(f, "plain"),
+            Self::Kernel => write!(f, "kernel"),
+            Self::Custom => write!(f, "custom"),
+        }
+    }
+}
+
+/// What to do with a generated message that still breaks the style after cleanup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum InvalidMessagePolicy {
+    /// Apply automatic fixes and show any remaining problems
+    #[default]
+    Fix,
+    /// Ask the model again, listing the problems
+    Reprompt,
+    /// Fail without committing
+    Reject,
+}
+
+impl std::fmt::Display for InvalidMessagePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fix => write!(f, "fix"),
+            Self::Reprompt => write!(f, "reprompt"),
+            Self::Reject => write!(f, "reject"),
+        }
+    }
+}
+
+/// A message style together with its prompt fragment, subject validator and fixer.
+#[derive(Debug, Clone)]
+pub struct StyleGuide {
+    pub style: MessageStyle,
+    pub on_invalid: InvalidMessagePolicy,
+    types: Vec<String>,
+    scopes: Vec<String>,
+    max_subject_length: usize,
+    custom_prompt: Option<String>,
+    custom_pattern: Option<Regex>,
+}
+
+impl StyleGuide {
+    pub fn from_config(config: &GitConfig) -> Result<Self> {
+        let style = config.message_style();
+
+        let custom_pattern = match (style, config.custom_style_pattern.as_deref()) {
+            (MessageStyle::Custom, Some(pattern)) => Some(
+                Regex::new(pattern)
+                    .map_err(|e| AppError::InvalidStyle(format!("bad custom pattern: {e}")))?,
+            ),
+            _ => None,
+        };
+
+        if style == MessageStyle::Custom && config.custom_style_prompt.is_none() {
+            return Err(AppError::InvalidStyle(
+                "the custom style requires git.custom_style_prompt".to_string(),
+            )
+            .into());
+        }
+
+        Ok(Self {
+            style,
+            on_invalid: config.on_invalid,
+            types: config.commit_types.clone(),
+            scopes: config.commit_scopes.clone(),
+            max_subject_length: config.max_subject_length,
+            custom_prompt: config.custom_style_prompt.clone(),
+            custom_pattern,
+        })
+    }
+
+    /// Prompt fragment describing the expected format.
+    pub fn instructions(&self) -> String {
+        let mut instructions = match self.style {
+            MessageStyle::Conventional => CONVENTIONAL_PROMPT.to_string(),
+            MessageStyle::Gitmoji => GITMOJI_PROMPT.to_string(),
+            MessageStyle::Plain => PLAIN_PROMPT.to_string(),
+            MessageStyle::Kernel => KERNEL_PROMPT.to_string(),
+            MessageStyle::Custom => self.custom_prompt.clone().unwrap_or_default(),
+        };
+
+        if !instructions.ends_with('\n') {
+            instructions.push('\n');
+        }
+        instructions.push_str("\nConstraints:\n");
+        if self.style == MessageStyle::Conventional {
+            instructions.push_str(&format!("- Allowed types: {}\n", self.types.join(", ")));
+            if !self.scopes.is_empty() {
+                instructions.push_str(&format!("- Allowed scopes: {}\n", self.scopes.join(", ")));
+            }
+        }
+        instructions.push_str(&format!(
+            "- The subject must not exceed {} characters\n",
+            self.max_subject_length
+        ));
+
+        instructions
+    }
+
+    /// Repair what can be repaired mechanically: invented or capitalized types,
+    /// unknown scopes, a trailing period and an overlong subject.
+    pub fn fix(&self, message: &mut CommitMessage) {
+        let mut subject = message
+            .subject
+            .trim()
+            .trim_end_matches('.')
+            .trim_end()
+            .to_string();
+
+        if self.style == MessageStyle::Conventional
+            && let Some(mut parsed) = ConventionalSubject::parse(&subject)
+        {
+            parsed.commit_type = self.normalize_type(&parsed.commit_type);
+            if !self.scopes.is_empty() {
+                // Keep a scope that only differs in case, drop invented ones
+                parsed.scope = parsed
+                    .scope
+                    .map(|scope| scope.to_lowercase())
+                    .filter(|scope| self.scopes.contains(scope));
+            }
+            parsed.description = lowercase_first(&parsed.description);
+            subject = parsed.to_string();
+        }
+
+        message.subject = truncate_subject(&subject, self.max_subject_length);
+    }
+
+    fn normalize_type(&self, commit_type: &str) -> String {
+        let lower = commit_type.to_lowercase();
+        if self.types.contains(&lower) {
+            return lower;
+        }
+
+        TYPE_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == lower)
+            .map(|(_, canonical)| (*canonical).to_string())
+            .filter(|canonical| self.types.contains(canonical))
+            .unwrap_or(lower)
+    }
+
+    /// Apply the fixes the `on_invalid` policy allows and return the problems left.
+    pub fn enforce(&self, message: &mut CommitMessage) -> Vec<String> {
+        if self.on_invalid != InvalidMessagePolicy::Reject {
+            self.fix(message);
+        }
+        self.validate(message)
+    }
+
+    /// Check the subject line against the style, returning every violation found.
+    pub fn validate(&self, message: &CommitMessage) -> Vec<String> {
+        let subject = message.subject.trim();
+        let mut problems = Vec::new();
+
+        if subject.is_empty() {
+            problems.push("subject line is empty".to_string());
+            return problems;
+        }
+
+        match self.style {
+            MessageStyle::Conventional => match ConventionalSubject::parse(subject) {
+                Some(parsed) => {
+                    if !self.types.contains(&parsed.commit_type) {
+                        problems.push(format!("unknown commit type `{}`", parsed.commit_type));
+                    }
+                    if let Some(scope) = &parsed.scope
+                        && !self.scopes.is_empty()
+                        && !self.scopes.contains(scope)
+                    {
+                        problems.push(format!("scope `{scope}` is not allowed"));
+                    }
+                    if parsed.description.is_empty() {
+                        problems.push("description is empty".to_string());
+                    }
+                }
+                None => problems.push("subject is not `type(scope): description`".to_string()),
+            },
+            MessageStyle::Gitmoji => {
+                if !GITMOJI_RE.is_match(subject) {
+                    problems.push("subject does not start with an emoji".to_string());
+                }
+            }
+            MessageStyle::Plain => {
+                if PREFIX_RE.is_match(subject) {
+                    problems.push("subject has a type or subsystem prefix".to_string());
+                }
+                if subject.starts_with(|c: char| c.is_lowercase()) {
+                    problems.push("subject does not start with a capital letter".to_string());
+                }
+            }
+            MessageStyle::Kernel => {
+                if !KERNEL_RE.is_match(subject) {
+                    problems.push("subject is not `subsystem: summary`".to_string());
+                }
+            }
+            MessageStyle::Custom => {
+                if let Some(pattern) = &self.custom_pattern
+                    && !pattern.is_match(subject)
+                {
+                    problems.push(format!("subject does not match `{pattern}`"));
+                }
+            }
+        }
+
+        if subject.ends_with('.') {
+            problems.push("subject ends with a period".to_string());
+        }
+
+        let length = subject.chars().count();
+        if length > self.max_subject_length {
+            problems.push(format!(
+                "subject is {length} characters, limit is {}",
+                self.max_subject_length
+            ));
+        }
+
+        problems
+    }
+}
+
+/// Lowercase the first letter unless the first word looks like an acronym.
+fn lowercase_first(text: &str) -> String {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(first), Some(second)) if first.is_uppercase() && !second.is_uppercase() => {
+            first.to_lowercase().chain(text.chars().skip(1)).collect()
+        }
+        _ => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+
+    fn guide(style: MessageStyle, on_invalid: InvalidMessagePolicy) -> StyleGuide {
+        let mut config = AppConfig::default().git;
+        config.style = Some(style);
+        config.on_invalid = on_invalid;
+        config.commit_scopes = vec!["cli".to_string(), "git".to_string()];
+        config.max_subject_length = 50;
+        if style == MessageStyle::Custom {
+            config.custom_style_prompt = Some("Start with a ticket key.".to_string());
+            config.custom_style_pattern = Some(r"^[A-Z]+-\d+ \S".to_string());
+        }
+        StyleGuide::from_config(&config).expect("valid style config")
+    }
+
+    /// Fix `subject` in the given style, returning it and the problems left.
+    fn fixed(style: MessageStyle, subject: &str) -> (String, Vec<String>) {
+        let guide = guide(style, InvalidMessagePolicy::Fix);
+        let mut message = CommitMessage::parse(subject);
+        let problems = guide.enforce(&mut message);
+        (message.subject, problems)
+    }
+
+    fn problems(style: MessageStyle, subject: &str) -> Vec<String> {
+        guide(style, InvalidMessagePolicy::Reject).validate(&CommitMessage::parse(subject))
+    }
+
+    #[test]
+    fn conventional_subjects() {
+        let style = MessageStyle::Conventional;
+        assert!(problems(style, "feat(cli): add --include filter").is_empty());
+        assert!(problems(style, "fix!: drop legacy api key").is_empty());
+
+        assert_eq!(
+            fixed(style, "Feature(CLI): Add filters."),
+            ("feat(cli): add filters".to_string(), Vec::new())
+        );
+        assert_eq!(
+            fixed(style, "bugfix(parser): handle empty input"),
+            ("fix: handle empty input".to_string(), Vec::new())
+        );
+        assert_eq!(
+            fixed(
+                style,
+                "docs: describe the new --include and --exclude path filters"
+            ),
+            (
+                "docs: describe the new --include and --exclude".to_string(),
+                Vec::new()
+            )
+        );
+        assert_eq!(fixed(style, "feat: CLI flags").0, "feat: CLI flags");
+
+        assert_eq!(
+            problems(style, "wip(parser): stuff."),
+            [
+                "unknown commit type `wip`",
+                "scope `parser` is not allowed",
+                "subject ends with a period",
+            ]
+        );
+        assert_eq!(
+            fixed(style, "Add path filters").1,
+            ["subject is not `type(scope): description`"]
+        );
+    }
+
+    #[test]
+    fn gitmoji_subjects() {
+        let style = MessageStyle::Gitmoji;
+        assert!(problems(style, "✨ Add path filters").is_empty());
+        assert!(problems(style, ":bug: Fix staging of nested repos").is_empty());
+
+        assert_eq!(
+            fixed(style, "🐛 Fix staging of nested repos."),
+            ("🐛 Fix staging of nested repos".to_string(), Vec::new())
+        );
+
+        assert_eq!(
+            fixed(style, "Fix staging").1,
+            ["subject does not start with an emoji"]
+        );
+    }
+
+    #[test]
+    fn plain_subjects() {
+        let style = MessageStyle::Plain;
+        assert!(problems(style, "Add path filters to the commit command").is_empty());
+
+        assert_eq!(
+            fixed(style, "Add path filters."),
+            ("Add path filters".to_string(), Vec::new())
+        );
+
+        assert_eq!(
+            problems(style, "feat: add path filters"),
+            [
+                "subject has a type or subsystem prefix",
+                "subject does not start with a capital letter",
+            ]
+        );
+    }
+
+    #[test]
+    fn kernel_subjects() {
+        let style = MessageStyle::Kernel;
+        assert!(problems(style, "git: skip embedded repositories").is_empty());
+        assert!(problems(style, "net/ipv4, net/ipv6: fix checksum").is_empty());
+
+        assert_eq!(
+            fixed(style, "git: skip embedded repositories."),
+            ("git: skip embedded repositories".to_string(), Vec::new())
+        );
+
+        assert_eq!(
+            fixed(style, "Git: Skip embedded repositories").1,
+            ["subject is not `subsystem: summary`"]
+        );
+    }
+
+    #[test]
+    fn custom_subjects() {
+        let style = MessageStyle::Custom;
+        assert!(problems(style, "ABC-12 Add path filters").is_empty());
+
+        assert_eq!(
+            fixed(style, "ABC-12 Add path filters."),
+            ("ABC-12 Add path filters".to_string(), Vec::new())
+        );
+
+        assert_eq!(
+            fixed(style, "Add path filters").1,
+            [r"subject does not match `^[A-Z]+-\d+ \S`"]
+        );
+    }
+
+    #[test]
+    fn custom_style_needs_a_prompt_and_a_valid_pattern() {
+        let mut config = AppConfig::default().git;
+        config.style = Some(MessageStyle::Custom);
+        assert!(StyleGuide::from_config(&config).is_err());
+
+        config.custom_style_prompt = Some("Start with a ticket key.".to_string());
+        config.custom_style_pattern = Some("(".to_string());
+        assert!(StyleGuide::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn empty_subjects_are_rejected_in_every_style() {
+        for style in MessageStyle::value_variants() {
+            assert_eq!(fixed(*style, "").1, ["subject line is empty"]);
+        }
+    }
+
+    #[test]
+    fn on_invalid_decides_whether_messages_are_fixed() {
+        let subject = "Feature(cli): Add filters.";
+
+        for policy in [InvalidMessagePolicy::Fix, InvalidMessagePolicy::Reprompt] {
+            let mut message = CommitMessage::parse(subject);
+            let problems = guide(MessageStyle::Conventional, policy).enforce(&mut message);
+            assert_eq!(message.subject, "feat(cli): add filters");
+            assert!(problems.is_empty());
+        }
+
+        let mut message = CommitMessage::parse(subject);
+        let problems =
+            guide(MessageStyle::Conventional, InvalidMessagePolicy::Reject).enforce(&mut message);
+        assert_eq!(message.subject, subject);
+        assert_eq!(
+            problems,
+            [
+                "unknown commit type `Feature`",
+                "subject ends with a period",
+            ]
+        );
+    }
+}
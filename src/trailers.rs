@@ -0,0 +1,267 @@
+use std::sync::LazyLock;
+
+use anyhow::Result;
+use regex::Regex;
+
+use crate::{
+    config::GitConfig,
+    error::AppError,
+    message::{CommitMessage, ConventionalSubject, Footer},
+    style::MessageStyle,
+};
+
+pub const BREAKING_CHANGE: &str = "BREAKING CHANGE";
+pub const CO_AUTHORED_BY: &str = "Co-authored-by";
+pub const SIGNED_OFF_BY: &str = "Signed-off-by";
+
+/// Jira-style keys such as `PROJ-123`.
+const DEFAULT_ISSUE_PATTERN: &str = r"\b[A-Z][A-Z0-9]+-\d+\b";
+
+/// GitHub-style issue numbers: `issue-42`, `gh-42`, or leading the segment after a
+/// `fix/` or `feature/` prefix as in `fix/123-crash`. Other numbers, like the date in
+/// `release/2024-10`, are not issues.
+static ISSUE_NUMBER_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:(?:^|/)(?:issue|gh)-|(?:^|/)(?:feature|feat|fix|bugfix|hotfix)/)(?<number>\d+)(?:[-_]|$)")
+        .expect("valid issue number regex")
+});
+static IDENTITY_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[^<>]+ <[^<>\s]+@[^<>\s]+>$").expect("valid identity regex"));
+static PUBLIC_ITEM_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^\s*(?:pub\s+(?:async\s+)?(?:unsafe\s+)?(?:fn|struct|enum|trait|type|const|static|mod)|export\s+(?:default\s+)?(?:async\s+)?(?:function|class|interface|type|const|enum))\s+(?<name>[A-Za-z_][A-Za-z0-9_]*)",
+    )
+    .expect("valid public item regex")
+});
+
+/// Trailers appended to a generated message, in git's `Token: value` format.
+#[derive(Debug, Clone, Default)]
+pub struct Trailers {
+    issue_token: String,
+    issue_keys: Vec<String>,
+    removed_items: Vec<String>,
+    co_authors: Vec<String>,
+    signoff: Option<String>,
+}
+
+impl Trailers {
+    pub fn new(
+        config: &GitConfig,
+        branch: Option<&str>,
+        diff: &str,
+        co_authors: &[String],
+    ) -> Result<Self> {
+        for co_author in co_authors {
+            if !IDENTITY_RE.is_match(co_author.trim()) {
+                return Err(AppError::InvalidCoAuthor(co_author.clone()).into());
+            }
+        }
+
+        let issue_keys = match branch {
+            Some(branch) if config.issue_from_branch => {
+                issue_keys_from_branch(branch, config.issue_pattern.as_deref())?
+            }
+            _ => Vec::new(),
+        };
+
+        // Removed items are only a hint to the model unless detection is enabled
+        let removed_items = if config.detect_breaking {
+            removed_public_items(diff)
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            issue_token: config.issue_trailer.clone(),
+            issue_keys,
+            removed_items,
+            co_authors: co_authors.iter().map(|c| c.trim().to_string()).collect(),
+            signoff: None,
+        })
+    }
+
+    /// Add a `Signed-off-by` trailer for `identity` (`Name <email>`).
+    pub fn with_signoff(mut self, identity: String) -> Self {
+        self.signoff = Some(identity);
+        self
+    }
+
+    /// Add the trailers to `message`, keeping any the model already wrote.
+    pub fn apply(&self, message: &mut CommitMessage, style: MessageStyle) {
+        let mut breaking = message
+            .footers
+            .iter()
+            .any(|footer| footer.token == BREAKING_CHANGE);
+        let conventional = (style == MessageStyle::Conventional)
+            .then(|| ConventionalSubject::parse(&message.subject))
+            .flatten();
+
+        // Removed public items the model did not call out still break callers
+        if !breaking
+            && !conventional
+                .as_ref()
+                .is_some_and(|subject| subject.breaking)
+            && !self.removed_items.is_empty()
+        {
+            let items: Vec<String> = self
+                .removed_items
+                .iter()
+                .map(|item| format!("`{item}`"))
+                .collect();
+            message.footers.insert(
+                0,
+                Footer::new(BREAKING_CHANGE, format!("removes {}", items.join(", "))),
+            );
+            breaking = true;
+        }
+
+        if breaking && let Some(mut subject) = conventional {
+            subject.breaking = true;
+            message.subject = subject.to_string();
+        }
+
+        for key in &self.issue_keys {
+            let mentioned = message
+                .footers
+                .iter()
+                .any(|footer| footer.value.contains(key.as_str()));
+            if !mentioned {
+                message
+                    .footers
+                    .push(Footer::new(self.issue_token.clone(), key.clone()));
+            }
+        }
+        for co_author in &self.co_authors {
+            push_unique(message, CO_AUTHORED_BY, co_author);
+        }
+        if let Some(identity) = &self.signoff {
+            push_unique(message, SIGNED_OFF_BY, identity);
+        }
+    }
+}
+
+fn push_unique(message: &mut CommitMessage, token: &str, value: &str) {
+    let exists = message
+        .footers
+        .iter()
+        .any(|footer| footer.token.eq_ignore_ascii_case(token) && footer.value == value);
+    if !exists {
+        message.footers.push(Footer::new(token, value));
+    }
+}
+
+/// Issue keys named in a branch such as `feature/PROJ-123-login` or `fix/42-crash`.
+pub fn issue_keys_from_branch(branch: &str, pattern: Option<&str>) -> Result<Vec<String>> {
+    let pattern = Regex::new(pattern.unwrap_or(DEFAULT_ISSUE_PATTERN))
+        .map_err(|e| AppError::InvalidIssuePattern(e.to_string()))?;
+
+    let mut keys: Vec<String> = pattern
+        .find_iter(branch)
+        .map(|m| m.as_str().to_string())
+        .collect();
+    if keys.is_empty() {
+        keys = ISSUE_NUMBER_RE
+            .captures_iter(branch)
+            .map(|caps| format!("#{}", &caps["number"]))
+            .collect();
+    }
+    keys.dedup();
+
+    Ok(keys)
+}
+
+/// Names of public items whose definition the diff removes without re-adding.
+///
+/// Files under `tests/`, `benches/`, `examples/` and `src/bin/` are skipped, since
+/// nothing outside them can call their items.
+pub fn removed_public_items(diff: &str) -> Vec<String> {
+    let definitions = |prefix: char| -> Vec<String> {
+        let mut public_file = true;
+        let mut names = Vec::new();
+        for line in diff.lines() {
+            if let Some(header) = line.strip_prefix("diff --git ") {
+                let path = header.rsplit_once(" b/").map_or(header, |(_, path)| path);
+                public_file = is_public_path(path);
+            } else if public_file
+                && let Some(line) = line.strip_prefix(prefix)
+                && let Some(caps) = PUBLIC_ITEM_RE.captures(line)
+            {
+                names.push(caps["name"].to_string());
+            }
+        }
+        names
+    };
+
+    let added = definitions('+');
+    let mut removed: Vec<String> = definitions('-')
+        .into_iter()
+        .filter(|name| !added.contains(name))
+        .collect();
+    removed.sort();
+    removed.dedup();
+    removed
+}
+
+/// Whether items defined in `path` can be used by other crates or packages.
+fn is_public_path(path: &str) -> bool {
+    let in_dir =
+        |dir: &str| path.starts_with(&format!("{dir}/")) || path.contains(&format!("/{dir}/"));
+    !["tests", "benches", "examples", "src/bin"]
+        .into_iter()
+        .any(in_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn issue_keys_need_an_issue_prefix_or_branch_type() {
+        let keys = |branch| issue_keys_from_branch(branch, None).expect("valid pattern");
+        assert_eq!(keys("feature/PROJ-123-login"), ["PROJ-123"]);
+        assert_eq!(keys("fix/42-crash"), ["#42"]);
+        assert_eq!(keys("feature/7"), ["#7"]);
+        assert_eq!(keys("issue-42"), ["#42"]);
+        assert_eq!(keys("user/gh-9_cleanup"), ["#9"]);
+        assert!(keys("release/2024-10").is_empty());
+        assert!(keys("2024-10-hotfix").is_empty());
+        assert!(keys("main").is_empty());
+    }
+
+    #[test]
+    fn removed_items_skip_tests_benches_examples_and_binaries() {
+        let diff = "\
+diff --git a/src/lib.rs b/src/lib.rs
+-pub fn parse() {}
+diff --git a/tests/common.rs b/tests/common.rs
+-pub fn helper() {}
+diff --git a/crates/core/benches/speed.rs b/crates/core/benches/speed.rs
+-pub fn bench() {}
+diff --git a/src/bin/tool.rs b/src/bin/tool.rs
+-pub struct Args;
+diff --git a/web/src/api.ts b/web/src/api.ts
+-export function fetchUser() {}
++export function fetchUser(id) {}
+";
+        assert_eq!(removed_public_items(diff), ["parse"]);
+    }
+
+    #[test]
+    fn removed_items_are_only_marked_breaking_when_enabled() {
+        let diff = "diff --git a/src/lib.rs b/src/lib.rs\n-pub fn parse() {}\n";
+        let mut config = crate::config::AppConfig::default().git;
+        let mut message = CommitMessage::parse("refactor: drop parse");
+        Trailers::new(&config, None, diff, &[])
+            .expect("trailers")
+            .apply(&mut message, MessageStyle::Conventional);
+        assert_eq!(message.to_string(), "refactor: drop parse");
+
+        config.detect_breaking = true;
+        Trailers::new(&config, None, diff, &[])
+            .expect("trailers")
+            .apply(&mut message, MessageStyle::Conventional);
+        assert_eq!(
+            message.to_string(),
+            "refactor!: drop parse\n\nBREAKING CHANGE: removes `parse`"
+        );
+    }
+}